@@ -0,0 +1,408 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use flate2::bufread::GzDecoder;
+use log::info;
+use zip::ZipArchive;
+
+use crate::error::{Result as AppResult, ResultExt};
+use crate::progress::{Progress, ProgressReader};
+
+pub const ZIP_FILE_EXT: &str = ".zip";
+pub const TAR_GZ_FILE_EXT: &str = ".tar.gz";
+// packed mods distributed through CCModDB are zip archives with the mod files
+// at the top level
+pub const CCMOD_FILE_EXT: &str = ".ccmod";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+  TarGz,
+  Zip,
+}
+
+impl ArchiveFormat {
+  // the format is guessed from the file extension because that's what the
+  // URLs in CCModDB are based on
+  pub fn from_file_name(file_name: &str) -> Option<Self> {
+    if file_name.ends_with(TAR_GZ_FILE_EXT) {
+      Some(ArchiveFormat::TarGz)
+    } else if file_name.ends_with(ZIP_FILE_EXT)
+      || file_name.ends_with(CCMOD_FILE_EXT)
+    {
+      Some(ArchiveFormat::Zip)
+    } else {
+      None
+    }
+  }
+
+  pub fn file_ext(self) -> &'static str {
+    match self {
+      ArchiveFormat::TarGz => TAR_GZ_FILE_EXT,
+      ArchiveFormat::Zip => ZIP_FILE_EXT,
+    }
+  }
+}
+
+// Extracts every entry of the archive for which `filter` returns true into
+// `unpack_dir`, keeping the paths from the archive. Entries which would end up
+// outside of `unpack_dir` are skipped.
+pub fn unpack_archive(
+  archive_path: &Path,
+  unpack_dir: &Path,
+  progress: Rc<dyn Progress>,
+  filter: &dyn Fn(&Path) -> bool,
+) -> AppResult<()> {
+  let format = archive_format(archive_path)?;
+
+  let archive_file = File::open(archive_path).with_context(|_| {
+    format!("couldn't open file '{}'", archive_path.display())
+  })?;
+  let archive_size: u64 = archive_file
+    .metadata()
+    .with_context(|_| {
+      format!("couldn't stat file '{}'", archive_path.display())
+    })?
+    .len();
+
+  fs::create_dir_all(unpack_dir).with_context(|_| {
+    format!("couldn't create directory '{}'", unpack_dir.display())
+  })?;
+
+  match format {
+    ArchiveFormat::TarGz => {
+      unpack_tar_gz(archive_file, archive_size, unpack_dir, progress, filter)
+    }
+    ArchiveFormat::Zip => {
+      unpack_zip(archive_file, archive_size, unpack_dir, progress, filter)
+    }
+  }
+}
+
+fn archive_format(archive_path: &Path) -> AppResult<ArchiveFormat> {
+  archive_path
+    .file_name()
+    .and_then(|name| name.to_str())
+    .and_then(ArchiveFormat::from_file_name)
+    .ok_or_else(|| {
+      format!("unsupported archive format: '{}'", archive_path.display())
+    })
+}
+
+// Returns the name of the directory which contains all other entries of the
+// archive, e.g. `CCLoader-2.22.1` in the release archives of CCLoader, or
+// `None` if there isn't one.
+pub fn root_dir_name(archive_path: &Path) -> AppResult<Option<String>> {
+  let format = archive_format(archive_path)?;
+  let archive_file = File::open(archive_path).with_context(|_| {
+    format!("couldn't open file '{}'", archive_path.display())
+  })?;
+
+  let mut entry_paths: Vec<PathBuf> = Vec::new();
+  match format {
+    ArchiveFormat::TarGz => {
+      let mut archive =
+        tar::Archive::new(GzDecoder::new(BufReader::new(archive_file)));
+      for entry in archive.entries().context("archive error")? {
+        let entry = entry.context("archive read I/O error")?;
+        // the archives generated by GitHub start with a header of this kind,
+        // which isn't a file
+        if entry.header().entry_type() == tar::EntryType::XGlobalHeader {
+          continue;
+        }
+        if let Ok(entry_path) = entry.path() {
+          entry_paths.push(entry_path.into_owned());
+        }
+      }
+    }
+    ArchiveFormat::Zip => {
+      let mut archive = ZipArchive::new(BufReader::new(archive_file))
+        .context("archive error")?;
+      for index in 0..archive.len() {
+        let entry = archive.by_index(index).context("archive read error")?;
+        if let Some(entry_path) = entry.enclosed_name() {
+          entry_paths.push(entry_path.to_owned());
+        }
+      }
+    }
+  }
+
+  let mut root_dir_name = None;
+  for entry_path in &entry_paths {
+    let first_component =
+      entry_path.components().find(|c| *c != Component::CurDir);
+    let name = match first_component {
+      Some(Component::Normal(name)) => name,
+      _ => return Ok(None),
+    };
+    match &root_dir_name {
+      None => root_dir_name = Some(name),
+      Some(root_dir_name) if *root_dir_name == name => {}
+      Some(_) => return Ok(None),
+    }
+  }
+  Ok(root_dir_name.and_then(|name| name.to_str()).map(str::to_owned))
+}
+
+fn unpack_tar_gz(
+  archive_file: File,
+  archive_size: u64,
+  unpack_dir: &Path,
+  progress: Rc<dyn Progress>,
+  filter: &dyn Fn(&Path) -> bool,
+) -> AppResult<()> {
+  let mut decoder = GzDecoder::new(BufReader::new(ProgressReader::new(
+    archive_file,
+    archive_size,
+    progress,
+  )));
+  let mut archive = tar::Archive::new(&mut decoder);
+  archive.set_preserve_permissions(true);
+
+  for entry in archive.entries().context("archive error")? {
+    let mut entry = entry.context("archive read I/O error")?;
+    if let Ok(entry_path) = entry.path() {
+      let entry_path: PathBuf = entry_path.into_owned();
+      if !filter(&entry_path) {
+        continue;
+      }
+
+      info!("unpacking {}", entry_path.display());
+      // `unpack_in` refuses to write outside of the directory by itself
+      entry.unpack_in(unpack_dir).context("archive unpack I/O error")?;
+    }
+  }
+
+  Ok(())
+}
+
+fn unpack_zip(
+  archive_file: File,
+  archive_size: u64,
+  unpack_dir: &Path,
+  progress: Rc<dyn Progress>,
+  filter: &dyn Fn(&Path) -> bool,
+) -> AppResult<()> {
+  // zip archives have to be seekable because the list of entries is at the
+  // end of the file, so the progress is computed from the offsets of the
+  // entries instead of wrapping the file in a ProgressReader
+  let mut archive =
+    ZipArchive::new(BufReader::new(archive_file)).context("archive error")?;
+
+  for index in 0..archive.len() {
+    if progress.is_cancelled() {
+      bail!("cancelled by the user");
+    }
+
+    let mut entry = archive.by_index(index).context("archive read error")?;
+    if archive_size > 0 {
+      progress
+        .set_fraction(Some(entry.data_start() as f64 / archive_size as f64));
+    }
+
+    let entry_path: PathBuf = match entry.enclosed_name() {
+      Some(path) => path.to_owned(),
+      None => continue,
+    };
+    if !filter(&entry_path) {
+      continue;
+    }
+
+    info!("unpacking {}", entry_path.display());
+    let dest_path = unpack_dir.join(&entry_path);
+    if entry.is_dir() {
+      fs::create_dir_all(&dest_path).context("archive unpack I/O error")?;
+      continue;
+    }
+
+    if let Some(parent) = dest_path.parent() {
+      fs::create_dir_all(parent).context("archive unpack I/O error")?;
+    }
+    let mut dest_file =
+      File::create(&dest_path).context("archive unpack I/O error")?;
+    io::copy(&mut entry, &mut dest_file).context("archive unpack I/O error")?;
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      if let Some(mode) = entry.unix_mode() {
+        fs::set_permissions(&dest_path, fs::Permissions::from_mode(mode))
+          .context("archive unpack I/O error")?;
+      }
+    }
+  }
+
+  progress.set_fraction(Some(1.0));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::progress::LogProgress;
+  use flate2::write::GzEncoder;
+  use flate2::Compression;
+  use std::io::Write;
+  use zip::write::FileOptions;
+  use zip::{CompressionMethod, ZipWriter};
+
+  // a fresh directory for every test, the tests run in parallel
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+      "ccloader-installer-test-{}-archive-{}",
+      std::process::id(),
+      name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  // names ending with a slash are directories
+  fn write_zip(path: &Path, entries: &[&str]) {
+    let mut writer = ZipWriter::new(File::create(path).unwrap());
+    let options =
+      FileOptions::default().compression_method(CompressionMethod::Stored);
+    for &name in entries {
+      if name.ends_with('/') {
+        writer.add_directory(name, options).unwrap();
+      } else {
+        writer.start_file(name, options).unwrap();
+        writer.write_all(name.as_bytes()).unwrap();
+      }
+    }
+    writer.finish().unwrap();
+  }
+
+  // starts with a global header like the archives generated by GitHub
+  fn write_tar_gz(path: &Path, entries: &[&str]) {
+    let encoder =
+      GzEncoder::new(File::create(path).unwrap(), Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+
+    let comment = b"52 comment=0123456789abcdef0123456789abcdef01234567\n";
+    let mut header = tar::Header::new_ustar();
+    header.set_path("pax_global_header").unwrap();
+    header.set_entry_type(tar::EntryType::XGlobalHeader);
+    header.set_size(comment.len() as u64);
+    header.set_cksum();
+    builder.append(&header, &comment[..]).unwrap();
+
+    for &name in entries {
+      let mut header = tar::Header::new_ustar();
+      header.set_path(name).unwrap();
+      header.set_mode(0o644);
+      header.set_size(name.len() as u64);
+      header.set_cksum();
+      builder.append(&header, name.as_bytes()).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+  }
+
+  fn unpack(archive_path: &Path, unpack_dir: &Path) -> AppResult<()> {
+    unpack_archive(
+      archive_path,
+      unpack_dir,
+      Rc::new(LogProgress::new()),
+      &|path| !path.starts_with("CCLoader-2.22.1/skipped"),
+    )
+  }
+
+  #[test]
+  fn unpacks_zip_archives() {
+    let dir = test_dir("unpack-zip");
+    let archive_path = dir.join("CCLoader-2.22.1.zip");
+    write_zip(
+      &archive_path,
+      &[
+        "CCLoader-2.22.1/",
+        "CCLoader-2.22.1/ccloader/",
+        "CCLoader-2.22.1/ccloader/index.html",
+        "CCLoader-2.22.1/assets/mods/simplify/package.json",
+        "CCLoader-2.22.1/skipped/file.txt",
+      ],
+    );
+
+    let unpack_dir = dir.join("unpacked");
+    unpack(&archive_path, &unpack_dir).unwrap();
+    let root = unpack_dir.join("CCLoader-2.22.1");
+    assert_eq!(
+      fs::read_to_string(root.join("ccloader/index.html")).unwrap(),
+      "CCLoader-2.22.1/ccloader/index.html"
+    );
+    assert!(root.join("assets/mods/simplify/package.json").is_file());
+    assert!(!root.join("skipped").exists());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn skips_zip_entries_outside_of_the_unpack_dir() {
+    let dir = test_dir("zip-slip");
+    let archive_path = dir.join("evil.zip");
+    write_zip(
+      &archive_path,
+      &["../outside.txt", "CCLoader-2.22.1/../../outside.txt", "inside.txt"],
+    );
+
+    let mut archive =
+      ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+    assert_eq!(archive.by_index(0).unwrap().name(), "../outside.txt");
+    assert!(archive.by_index(0).unwrap().enclosed_name().is_none());
+
+    let unpack_dir = dir.join("unpacked");
+    unpack(&archive_path, &unpack_dir).unwrap();
+    assert!(unpack_dir.join("inside.txt").is_file());
+    assert_eq!(fs::read_dir(&unpack_dir).unwrap().count(), 1);
+    assert!(!dir.join("outside.txt").exists());
+    assert!(!dir.parent().unwrap().join("outside.txt").exists());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn finds_the_root_dir_of_github_tarballs() {
+    let dir = test_dir("root-dir-tar");
+    let archive_path = dir.join("CCLoader-2.22.1.tar.gz");
+    write_tar_gz(
+      &archive_path,
+      &["CCLoader-2.22.1/ccloader/index.html", "CCLoader-2.22.1/README.md"],
+    );
+    assert_eq!(
+      root_dir_name(&archive_path).unwrap().as_deref(),
+      Some("CCLoader-2.22.1")
+    );
+
+    // the global header isn't unpacked either
+    let unpack_dir = dir.join("unpacked");
+    unpack(&archive_path, &unpack_dir).unwrap();
+    assert!(unpack_dir.join("CCLoader-2.22.1/README.md").is_file());
+    assert!(!unpack_dir.join("pax_global_header").exists());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn finds_no_root_dir_in_flat_archives() {
+    let dir = test_dir("root-dir-zip");
+    let archive_path = dir.join("CCLoader-2.22.1.zip");
+    write_zip(&archive_path, &["CCLoader-2.22.1/README.md", "other/file.txt"]);
+    assert_eq!(root_dir_name(&archive_path).unwrap(), None);
+    write_zip(&archive_path, &["CCLoader-2.22.1/", "README.md"]);
+    assert_eq!(root_dir_name(&archive_path).unwrap(), None);
+    write_zip(&archive_path, &["CCLoader-2.22.1/", "CCLoader-2.22.1/a.txt"]);
+    assert_eq!(
+      root_dir_name(&archive_path).unwrap().as_deref(),
+      Some("CCLoader-2.22.1")
+    );
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn rejects_unknown_archive_formats() {
+    let dir = test_dir("unknown-format");
+    let archive_path = dir.join("CCLoader-2.22.1.rar");
+    fs::write(&archive_path, b"").unwrap();
+    assert!(root_dir_name(&archive_path).is_err());
+    assert!(unpack(&archive_path, &dir.join("unpacked")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
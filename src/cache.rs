@@ -0,0 +1,227 @@
+// An on-disk cache in the cache directory of the OS, e.g.
+// `~/.cache/ccloader-installer` on GNU/Linux. Responses of small requests,
+// such as the database, are revalidated with `ETag` and `Last-Modified` and
+// the cached copy is used when the server can't be reached. Archives are
+// stored by their SHA-256 checksums, so they are never downloaded twice.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{info, warn};
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::{Result as AppResult, ResultExt};
+use crate::http_client::{
+  header, HttpClient, Request as HttpRequest, StatusCode, Uri,
+};
+use crate::installer;
+
+const RESPONSES_DIR_NAME: &str = "responses";
+const ARCHIVES_DIR_NAME: &str = "archives";
+// maps the URLs of downloaded archives to their checksums, so that archives
+// which aren't listed with a checksum in the database can be found as well
+const ARCHIVE_INDEX_FILE_NAME: &str = "index.json";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+  ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+  if !ENABLED.load(Ordering::Relaxed) {
+    return None;
+  }
+  dirs::cache_dir().map(|dir| dir.join(crate::PKG_NAME))
+}
+
+#[derive(Debug)]
+struct CachedResponse {
+  etag: Option<String>,
+  last_modified: Option<String>,
+  body: Vec<u8>,
+}
+
+// Sends a GET request and returns the response body. A cached response is
+// revalidated with the server and is used as is when the request fails.
+pub fn fetch(client: &mut HttpClient, url: &str) -> AppResult<Vec<u8>> {
+  let entry_path: Option<PathBuf> = cache_dir().map(|dir| {
+    dir.join(RESPONSES_DIR_NAME).join(installer::sha256_hex(url.as_bytes()))
+  });
+  let cached: Option<CachedResponse> =
+    entry_path.as_deref().and_then(read_cached_response);
+
+  let mut request = HttpRequest::get(url);
+  if let Some(cached) = &cached {
+    if let Some(etag) = &cached.etag {
+      request = request.header(header::IF_NONE_MATCH, etag.as_str());
+    }
+    if let Some(last_modified) = &cached.last_modified {
+      request =
+        request.header(header::IF_MODIFIED_SINCE, last_modified.as_str());
+    }
+  }
+
+  let failure = match client.send(request.body(Vec::new()).unwrap()) {
+    Ok(response) => {
+      let status = response.status();
+      match cached {
+        Some(cached) if status == StatusCode::NOT_MODIFIED => {
+          info!("{} hasn't changed, using the cached copy", url);
+          return Ok(cached.body);
+        }
+        _ if status.is_success() => {
+          let header_value = |name: header::HeaderName| {
+            response
+              .headers()
+              .get(name)
+              .and_then(|value| value.to_str().ok())
+              .map(|s| s.to_owned())
+          };
+          let response_to_cache = CachedResponse {
+            etag: header_value(header::ETAG),
+            last_modified: header_value(header::LAST_MODIFIED),
+            body: response.into_body(),
+          };
+          if let Some(entry_path) = &entry_path {
+            if let Err(e) =
+              write_cached_response(entry_path, &response_to_cache)
+            {
+              warn!("couldn't cache the response from {}: {}", url, e);
+            }
+          }
+          return Ok(response_to_cache.body);
+        }
+        _ => format!("HTTP error: {}", status),
+      }
+    }
+    Err(e) => format!("network error: {}", e),
+  };
+
+  match entry_path.as_deref().and_then(read_cached_response) {
+    Some(cached) => {
+      warn!("{}, using the cached copy of {}", failure, url);
+      Ok(cached.body)
+    }
+    None => Err(failure),
+  }
+}
+
+// the metadata is kept in a separate file, so that the body can be written
+// as it is
+fn read_cached_response(entry_path: &Path) -> Option<CachedResponse> {
+  let bytes = fs::read(entry_path.with_extension("json")).ok()?;
+  let data: JsonValue = serde_json::from_slice(&bytes).ok()?;
+  let body = fs::read(entry_path.with_extension("body")).ok()?;
+  Some(CachedResponse {
+    etag: data["etag"].as_str().map(|s| s.to_owned()),
+    last_modified: data["last_modified"].as_str().map(|s| s.to_owned()),
+    body,
+  })
+}
+
+fn write_cached_response(
+  entry_path: &Path,
+  response: &CachedResponse,
+) -> io::Result<()> {
+  if let Some(dir) = entry_path.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  // the old metadata mustn't end up next to the new body if writing fails
+  let metadata_path = entry_path.with_extension("json");
+  match fs::remove_file(&metadata_path) {
+    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+    _ => {}
+  }
+  fs::write(entry_path.with_extension("body"), &response.body)?;
+  let metadata = json!({
+    "etag": response.etag,
+    "last_modified": response.last_modified,
+  });
+  fs::write(metadata_path, serde_json::to_vec_pretty(&metadata).unwrap())
+}
+
+// Looks for an archive downloaded from `url` earlier, either by the checksum
+// from the database or, when there is none, by the URL. The checksum of the
+// cached file is checked in both cases.
+pub fn find_archive(
+  url: &Uri,
+  expected_sha256: Option<&str>,
+) -> Option<PathBuf> {
+  let archives_dir = cache_dir()?.join(ARCHIVES_DIR_NAME);
+  let sha256: String = match expected_sha256 {
+    Some(sha256) => sha256.to_owned(),
+    None => read_archive_index(&archives_dir).remove(&url.to_string())?,
+  };
+  let path = archives_dir.join(&sha256).join(archive_file_name(url));
+  if !path.is_file() {
+    return None;
+  }
+
+  match installer::sha256_file(&path) {
+    Ok(actual) if actual == sha256 => Some(path),
+    Ok(_) => {
+      warn!("the cached archive {} is corrupted, deleting it", path.display());
+      if let Err(e) = fs::remove_file(&path) {
+        warn!("couldn't delete '{}': {}", path.display(), e);
+      }
+      None
+    }
+    Err(e) => {
+      warn!("couldn't read '{}': {}", path.display(), e);
+      None
+    }
+  }
+}
+
+// Moves a downloaded archive into the cache and returns its new path, `None`
+// is returned when the cache is disabled.
+pub fn store_archive(url: &Uri, path: &Path) -> AppResult<Option<PathBuf>> {
+  let archives_dir = match cache_dir() {
+    Some(dir) => dir.join(ARCHIVES_DIR_NAME),
+    None => return Ok(None),
+  };
+  let sha256 = installer::sha256_file(path)
+    .with_context(|_| format!("couldn't read '{}'", path.display()))?;
+
+  let dir = archives_dir.join(&sha256);
+  fs::create_dir_all(&dir).with_context(|_| {
+    format!("couldn't create directory '{}'", dir.display())
+  })?;
+  let cached_path = dir.join(archive_file_name(url));
+  info!("moving {} to {}", path.display(), cached_path.display());
+  // the cache and the temporary directory may be on different file systems
+  if fs::rename(path, &cached_path).is_err() {
+    fs::copy(path, &cached_path).with_context(|_| {
+      format!("couldn't copy '{}' into the cache", path.display())
+    })?;
+    if let Err(e) = fs::remove_file(path) {
+      warn!("couldn't delete '{}': {}", path.display(), e);
+    }
+  }
+
+  // the archive can still be found by its checksum without the index
+  let mut index = read_archive_index(&archives_dir);
+  index.insert(url.to_string(), sha256);
+  let bytes = serde_json::to_vec_pretty(&index).unwrap();
+  if let Err(e) = fs::write(archives_dir.join(ARCHIVE_INDEX_FILE_NAME), bytes) {
+    warn!("couldn't update the index of the cached archives: {}", e);
+  }
+
+  Ok(Some(cached_path))
+}
+
+fn read_archive_index(archives_dir: &Path) -> BTreeMap<String, String> {
+  fs::read(archives_dir.join(ARCHIVE_INDEX_FILE_NAME))
+    .ok()
+    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    .unwrap_or_default()
+}
+
+// the name is kept because the format of an archive is determined by it
+fn archive_file_name(url: &Uri) -> &str {
+  url.path().rsplit('/').find(|s| !s.is_empty()).unwrap_or("download")
+}
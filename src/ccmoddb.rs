@@ -0,0 +1,250 @@
+// Access to CCModDB, the database of CrossCode mods. It is a single JSON
+// document (`npDatabase.json`) which maps package names to their metadata and
+// to the list of artifacts which can be installed.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+use crate::archive::ArchiveFormat;
+use crate::cache;
+use crate::error::{Result as AppResult, ResultExt};
+use crate::http_client::{HttpClient, Uri};
+
+pub const CCMODDB_DATA_URL: &str =
+  "https://raw.githubusercontent.com/CCDirectLink/CCModDB/refs/heads/stable/npDatabase.json";
+pub const CCMODDB_TESTING_DATA_URL: &str =
+  "https://raw.githubusercontent.com/CCDirectLink/CCModDB/refs/heads/testing/npDatabase.json";
+
+pub const CCLOADER_PACKAGE_NAME: &str = "ccloader";
+
+// CCModDB is published in two branches, new releases of the packages land in
+// `testing` before they are promoted to `stable`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Channel {
+  Stable,
+  Testing,
+}
+
+impl Channel {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "stable" => Some(Channel::Stable),
+      "testing" => Some(Channel::Testing),
+      _ => None,
+    }
+  }
+
+  pub fn database_url(self) -> &'static str {
+    match self {
+      Channel::Stable => CCMODDB_DATA_URL,
+      Channel::Testing => CCMODDB_TESTING_DATA_URL,
+    }
+  }
+}
+
+// Where a database is loaded from. Several databases (e.g. the official one and
+// a private mod index) can be used together, see `Database::load`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+  // the official database, the channel may be switched at runtime
+  Official(Channel),
+  Url(String),
+  File(PathBuf),
+}
+
+impl Source {
+  // `stable` and `testing` stand for the official database, anything else is
+  // either a URL or a path to a local copy
+  pub fn parse(text: &str) -> Self {
+    if let Some(channel) = Channel::from_name(text) {
+      Source::Official(channel)
+    } else if text.starts_with("http://") || text.starts_with("https://") {
+      Source::Url(text.to_owned())
+    } else {
+      Source::File(PathBuf::from(text))
+    }
+  }
+}
+
+// Makes the official databases among `sources` use the given channel, returns
+// `false` if there are none.
+pub fn switch_channel(sources: &mut [Source], channel: Channel) -> bool {
+  let mut found = false;
+  for source in sources {
+    if let Source::Official(source_channel) = source {
+      *source_channel = channel;
+      found = true;
+    }
+  }
+  found
+}
+
+impl fmt::Display for Source {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Source::Official(channel) => channel.database_url().fmt(f),
+      Source::Url(url) => url.fmt(f),
+      Source::File(path) => path.display().fmt(f),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+  packages: JsonMap<String, JsonValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Package {
+  pub name: String,
+  pub version: String,
+  // pairs of package names and semver requirements, `crosscode` and `nwjs`
+  // stand for the game and the runtime
+  pub dependencies: Vec<(String, String)>,
+  pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Artifact {
+  // either `modZip` or `ccmod`
+  pub kind: String,
+  pub url: Uri,
+  // the directory inside of the archive which contains the files of the
+  // package, the whole archive is used when it is absent
+  pub source: Option<String>,
+  // lowercase hex
+  pub sha256: Option<String>,
+}
+
+impl Database {
+  pub fn from_json(data: JsonValue) -> Option<Self> {
+    match data {
+      JsonValue::Object(packages) => Some(Self { packages }),
+      _ => None,
+    }
+  }
+
+  pub fn fetch(client: &mut HttpClient, url: &str) -> AppResult<Self> {
+    let body = cache::fetch(client, url)?;
+    let data: JsonValue = serde_json::from_slice(&body)
+      .context("invalid response received from CCModDB")?;
+    Self::from_json(data)
+      .ok_or_else(|| "invalid JSON data received from CCModDB".to_owned())
+  }
+
+  // Reads a local copy of the database, e.g. `npDatabase.json`.
+  pub fn read_file(path: &Path) -> AppResult<Self> {
+    let bytes = fs::read(path)
+      .with_context(|_| format!("couldn't read file '{}'", path.display()))?;
+    let data: JsonValue = serde_json::from_slice(&bytes)
+      .with_context(|_| format!("couldn't parse file '{}'", path.display()))?;
+    Self::from_json(data).ok_or_else(|| {
+      format!("'{}' isn't a valid CCModDB database", path.display())
+    })
+  }
+
+  // Loads the databases from all sources and merges them, a package listed by
+  // several databases is taken from the first one in which it appears.
+  pub fn load(client: &mut HttpClient, sources: &[Source]) -> AppResult<Self> {
+    let mut merged = JsonMap::new();
+    for source in sources {
+      info!("loading the database from {}", source);
+      let database = match source {
+        Source::Official(channel) => {
+          Self::fetch(client, channel.database_url())
+        }
+        Source::Url(url) => Self::fetch(client, url),
+        Source::File(path) => Self::read_file(path),
+      }
+      .with_context(|_| {
+        format!("couldn't load the database from {}", source)
+      })?;
+
+      for (name, data) in database.packages {
+        if merged.contains_key(&name) {
+          info!("{} from {} is overridden by another database", name, source);
+          continue;
+        }
+        merged.insert(name, data);
+      }
+    }
+    Ok(Self { packages: merged })
+  }
+
+  pub fn package(&self, name: &str) -> Option<Package> {
+    parse_package(name, self.packages.get(name)?)
+  }
+}
+
+impl Package {
+  // the artifact which contains the mod itself, ready to be put into the mods
+  // directory
+  pub fn mod_artifact(&self) -> Option<&Artifact> {
+    self
+      .artifacts
+      .iter()
+      .find(|artifact| artifact.kind == "modZip" || artifact.kind == "ccmod")
+  }
+
+  pub fn dependency(&self, name: &str) -> Option<&str> {
+    self
+      .dependencies
+      .iter()
+      .find(|(dep_name, _)| dep_name == name)
+      .map(|(_, req)| req.as_str())
+  }
+}
+
+fn parse_package(name: &str, data: &JsonValue) -> Option<Package> {
+  // packages described with ccmod.json keep their metadata in a separate field
+  let metadata = if data["metadataCCMod"].is_object() {
+    &data["metadataCCMod"]
+  } else {
+    &data["metadata"]
+  };
+  let version: String = metadata["version"].as_str()?.to_owned();
+
+  // ccmod.json lists the requirements in `dependencies`, package.json in
+  // `ccmodDependencies` or, in older packages, in `dependencies` too
+  let dependencies_data = if metadata["ccmodDependencies"].is_object() {
+    &metadata["ccmodDependencies"]
+  } else {
+    &metadata["dependencies"]
+  };
+  let dependencies: Vec<(String, String)> = dependencies_data
+    .as_object()
+    .map(|dependencies| {
+      dependencies
+        .iter()
+        .filter_map(|(name, req)| {
+          Some((name.clone(), req.as_str()?.to_owned()))
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+
+  let artifacts: Vec<Artifact> = data["installation"]
+    .as_array()?
+    .iter()
+    .filter_map(parse_artifact)
+    .collect();
+
+  Some(Package { name: name.to_owned(), version, dependencies, artifacts })
+}
+
+// artifacts in formats which can't be unpacked are skipped
+fn parse_artifact(data: &JsonValue) -> Option<Artifact> {
+  let url = data["url"].as_str()?;
+  ArchiveFormat::from_file_name(url)?;
+  Some(Artifact {
+    kind: data["type"].as_str()?.to_owned(),
+    url: Uri::try_from(url).ok()?,
+    source: data["source"].as_str().map(|s| s.to_owned()),
+    sha256: data["hash"]["sha256"].as_str().map(|s| s.to_ascii_lowercase()),
+  })
+}
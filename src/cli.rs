@@ -0,0 +1,1079 @@
+use std::ffi::OsString;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use semver::Version;
+use serde_json::{json, Value as JsonValue};
+
+use crate::cache;
+use crate::ccmoddb::{self, Channel, Database, Source};
+use crate::config;
+use crate::error::{Result as AppResult, ResultExt};
+use crate::game::Compatibility;
+use crate::http_client::{HttpClient, HttpClientConfig};
+use crate::installer::{self, InstallMode, ReleaseInfo, CCLOADER_DIR_PATH};
+use crate::manifest;
+use crate::mods;
+use crate::progress::LogProgress;
+use crate::resolver::{self, Environment, Step};
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE_ERROR: i32 = 2;
+pub const EXIT_ABORTED: i32 = 3;
+pub const EXIT_VERIFICATION_FAILED: i32 = 4;
+
+const USAGE_TEXT: &str = "\
+USAGE:
+    ccloader-installer [OPTIONS] <COMMAND>
+    ccloader-installer [OPTIONS] <install-mod|enable-mod|disable-mod> <NAME>...
+
+Runs in the headless mode when any arguments are given, otherwise starts the
+graphical installer.
+
+COMMANDS:
+    install      Install CCLoader into the game data directory
+    update       Update an existing CCLoader installation to the latest version
+    uninstall    Remove CCLoader and restore the vanilla game
+    verify       Check that an existing CCLoader installation is intact
+    repair       Restore the missing or changed files of CCLoader and patch
+                 package.json again
+    repatch      Only patch package.json to start CCLoader again, e.g. after
+                 the game has been updated by Steam
+    install-mod  Install mods from CCModDB by their package names, together
+                 with their dependencies
+    list-mods    List the installed mods and the available updates for them
+    update-mods  Update all mods installed from CCModDB to the latest versions
+    enable-mod   Let CCLoader load the given disabled mods again
+    disable-mod  Keep CCLoader from loading the given mods without deleting
+                 them
+
+OPTIONS:
+    -d, --game-dir <PATH>      Path to the CrossCode game data directory
+                               (autodetected when omitted)
+    -y, --yes                  Don't ask for confirmation
+        --db-url <URL>         URL of a CCModDB database to fetch CCLoader
+                               releases and mods from
+        --database <PATH>      Read a CCModDB database from a local file
+                               instead of downloading it
+        --channel <NAME>       Use the 'stable' (default) or the 'testing'
+                               branch of the official CCModDB
+        --ccloader-version <VERSION>
+                               Install the given CCLoader release instead of
+                               the latest one
+        --archive <PATH>       Install from a local CCLoader release archive
+                               (e.g. CCLoader-2.22.1.tar.gz) instead of
+                               downloading it
+        --remove-builtin-mods  Delete the built-in mods when uninstalling
+        --json                 Print the list of mods as JSON
+        --no-cache             Neither use nor fill the cache of downloaded
+                               databases and archives
+        --ignore-compatibility Install even if CCLoader or the mods don't
+                               support the version of the game
+        --connect-timeout <SECS>
+                               Give up connecting to a server after this many
+                               seconds (default: 30)
+        --stall-timeout <SECS> Abort a download which hasn't received any data
+                               for this many seconds (default: 60)
+        --retries <N>          Retry failed requests this many times
+                               (default: 3)
+    -h, --help                 Print this help message and exit
+    -V, --version              Print the version and exit
+
+DATABASES:
+    --db-url and --database can be repeated, the databases are merged and a
+    package listed by several of them is taken from the first one. Without
+    these options the list is read from the CCLOADER_INSTALLER_DATABASES
+    environment variable (separated with commas) or from the \"databases\"
+    array of the config file (e.g. ~/.config/ccloader-installer/config.json).
+    Every entry is a URL, a path or 'stable'/'testing' for the official
+    database.
+
+EXIT CODES:
+    0    success
+    1    the operation failed
+    2    invalid command-line arguments
+    3    the operation was aborted by the user
+    4    verification found problems with the installation
+";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Command {
+  Install,
+  Update,
+  Uninstall,
+  Verify,
+  Repair,
+  Repatch,
+  InstallMod,
+  ListMods,
+  UpdateMods,
+  EnableMod,
+  DisableMod,
+}
+
+impl Command {
+  // commands which take a list of mod names
+  fn takes_mod_names(self) -> bool {
+    matches!(
+      self,
+      Command::InstallMod | Command::EnableMod | Command::DisableMod
+    )
+  }
+}
+
+#[derive(Debug)]
+struct Options {
+  command: Command,
+  game_data_dir: Option<PathBuf>,
+  assume_yes: bool,
+  // `None` if no databases have been given on the command line
+  database_sources: Option<Vec<Source>>,
+  channel: Option<Channel>,
+  archive_path: Option<PathBuf>,
+  ccloader_version: Option<String>,
+  remove_builtin_mods: bool,
+  ignore_compatibility: bool,
+  mod_names: Vec<String>,
+  json_output: bool,
+  use_cache: bool,
+  http_config: HttpClientConfig,
+}
+
+// constructed only once, so the size of `Options` doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+enum ParsedArgs {
+  Run(Options),
+  PrintHelp,
+  PrintVersion,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Outcome {
+  Done,
+  Aborted,
+  VerificationFailed,
+}
+
+// Returns the command-line arguments without the program name, or `None` if
+// the installer was started without any, in which case the GUI is used.
+pub fn args() -> Option<Vec<OsString>> {
+  let args: Vec<OsString> = std::env::args_os()
+    .skip(1)
+    // macOS passes a process serial number argument to applications launched
+    // from Finder on older versions of the OS
+    .filter(|arg| !arg.to_string_lossy().starts_with("-psn_"))
+    .collect();
+  if args.is_empty() {
+    None
+  } else {
+    Some(args)
+  }
+}
+
+pub fn run(args: Vec<OsString>) -> i32 {
+  let options = match parse_args(args) {
+    Ok(ParsedArgs::Run(options)) => options,
+    Ok(ParsedArgs::PrintHelp) => {
+      print!("{}", USAGE_TEXT);
+      return EXIT_SUCCESS;
+    }
+    Ok(ParsedArgs::PrintVersion) => {
+      println!("{} {}", crate::PKG_NAME, crate::PKG_VERSION);
+      return EXIT_SUCCESS;
+    }
+    Err(message) => {
+      eprintln!("error: {}\n\n{}", message, USAGE_TEXT);
+      return EXIT_USAGE_ERROR;
+    }
+  };
+  info!("options = {:?}", options);
+
+  match try_run(&options) {
+    Ok(Outcome::Done) => EXIT_SUCCESS,
+    Ok(Outcome::Aborted) => {
+      warn!("aborted");
+      EXIT_ABORTED
+    }
+    Ok(Outcome::VerificationFailed) => EXIT_VERIFICATION_FAILED,
+    Err(error) => {
+      error!("{}", error);
+      EXIT_FAILURE
+    }
+  }
+}
+
+fn parse_args(args: Vec<OsString>) -> Result<ParsedArgs, String> {
+  let mut command = None;
+  let mut game_data_dir = None;
+  let mut assume_yes = false;
+  let mut database_sources = Vec::new();
+  let mut archive_path = None;
+  let mut channel = None;
+  let mut ccloader_version = None;
+  let mut remove_builtin_mods = false;
+  let mut ignore_compatibility = false;
+  let mut mod_names = Vec::new();
+  let mut json_output = false;
+  let mut use_cache = true;
+  let mut http_config = HttpClientConfig::default();
+
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let arg_str: String = match arg.to_str() {
+      Some(s) => s.to_owned(),
+      None => {
+        return Err(format!("invalid argument: {}", arg.to_string_lossy()))
+      }
+    };
+
+    // both `--option value` and `--option=value` forms are supported
+    let (name, mut inline_value): (&str, Option<OsString>) =
+      match arg_str.find('=') {
+        Some(index) if arg_str.starts_with("--") => {
+          (&arg_str[..index], Some(OsString::from(&arg_str[index + 1..])))
+        }
+        _ => (&arg_str, None),
+      };
+    let mut take_value = || -> Result<OsString, String> {
+      inline_value
+        .take()
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("option '{}' requires a value", name))
+    };
+    let mut take_number = || -> Result<u32, String> {
+      let value = take_value()?;
+      value.to_str().and_then(|s| s.parse().ok()).ok_or_else(|| {
+        format!(
+          "invalid value '{}' for option '{}', expected a number",
+          value.to_string_lossy(),
+          name
+        )
+      })
+    };
+
+    match name {
+      "-h" | "--help" => return Ok(ParsedArgs::PrintHelp),
+      "-V" | "--version" => return Ok(ParsedArgs::PrintVersion),
+      "-y" | "--yes" => assume_yes = true,
+      "--remove-builtin-mods" => remove_builtin_mods = true,
+      "--ignore-compatibility" => ignore_compatibility = true,
+      "--json" => json_output = true,
+      "--no-cache" => use_cache = false,
+      "-d" | "--game-dir" => game_data_dir = Some(PathBuf::from(take_value()?)),
+      "--db-url" => database_sources.push(Source::Url(
+        take_value()?
+          .into_string()
+          .map_err(|_| "the database URL is not valid Unicode".to_owned())?,
+      )),
+      "--database" => {
+        database_sources.push(Source::File(PathBuf::from(take_value()?)))
+      }
+      "--archive" => archive_path = Some(PathBuf::from(take_value()?)),
+      "--channel" => {
+        let value = take_value()?;
+        channel = Some(
+          value.to_str().and_then(Channel::from_name).ok_or_else(
+            || {
+              format!(
+                "invalid value '{}' for option '{}', expected 'stable' or 'testing'",
+                value.to_string_lossy(),
+                name
+              )
+            },
+          )?,
+        )
+      }
+      "--ccloader-version" => {
+        let value = take_value()?;
+        // both `2.22.1` and the tag name `v2.22.1` are accepted
+        let version = value
+          .to_str()
+          .map(|s| s.strip_prefix('v').unwrap_or(s))
+          .filter(|s| Version::parse(s).is_ok())
+          .ok_or_else(|| {
+            format!(
+              "invalid value '{}' for option '{}', expected a version such as 2.22.1",
+              value.to_string_lossy(),
+              name
+            )
+          })?;
+        ccloader_version = Some(version.to_owned())
+      }
+      "--connect-timeout" => {
+        http_config.connect_timeout =
+          Duration::from_secs(u64::from(take_number()?))
+      }
+      "--stall-timeout" => {
+        http_config.low_speed_time =
+          Duration::from_secs(u64::from(take_number()?))
+      }
+      "--retries" => http_config.max_retries = take_number()?,
+      _ if name.starts_with('-') => {
+        return Err(format!("unknown option '{}'", name))
+      }
+      _ if command.is_some_and(Command::takes_mod_names) => {
+        mod_names.push(arg_str.clone())
+      }
+      _ if command.is_some() => {
+        return Err(format!("unexpected argument '{}'", name))
+      }
+      "install" => command = Some(Command::Install),
+      "update" => command = Some(Command::Update),
+      "uninstall" => command = Some(Command::Uninstall),
+      "verify" => command = Some(Command::Verify),
+      "repair" => command = Some(Command::Repair),
+      "repatch" => command = Some(Command::Repatch),
+      "install-mod" => command = Some(Command::InstallMod),
+      "list-mods" => command = Some(Command::ListMods),
+      "update-mods" => command = Some(Command::UpdateMods),
+      "enable-mod" => command = Some(Command::EnableMod),
+      "disable-mod" => command = Some(Command::DisableMod),
+      _ => return Err(format!("unknown command '{}'", name)),
+    }
+
+    if inline_value.is_some() {
+      return Err(format!("option '{}' doesn't take a value", name));
+    }
+  }
+
+  if ccloader_version.is_some() && archive_path.is_some() {
+    return Err(
+      "options '--ccloader-version' and '--archive' can't be used together"
+        .to_owned(),
+    );
+  }
+
+  let command = command.ok_or_else(|| "no command specified".to_owned())?;
+  if command.takes_mod_names() && mod_names.is_empty() {
+    return Err("no mods specified".to_owned());
+  }
+  if command == Command::InstallMod && archive_path.is_some() {
+    return Err(
+      "option '--archive' can't be used with 'install-mod'".to_owned(),
+    );
+  }
+
+  if ccloader_version.is_some()
+    && !(command == Command::Install || command == Command::Update)
+  {
+    return Err(
+      "option '--ccloader-version' can only be used with 'install' or 'update'"
+        .to_owned(),
+    );
+  }
+  if json_output && command != Command::ListMods {
+    return Err("option '--json' can only be used with 'list-mods'".to_owned());
+  }
+
+  Ok(ParsedArgs::Run(Options {
+    command,
+    game_data_dir,
+    assume_yes,
+    database_sources: if database_sources.is_empty() {
+      None
+    } else {
+      Some(database_sources)
+    },
+    channel,
+    archive_path,
+    ccloader_version,
+    remove_builtin_mods,
+    ignore_compatibility,
+    mod_names,
+    json_output,
+    use_cache,
+    http_config,
+  }))
+}
+
+fn try_run(options: &Options) -> AppResult<Outcome> {
+  cache::set_enabled(options.use_cache);
+
+  let game_data_dir: PathBuf = match &options.game_data_dir {
+    Some(path) => {
+      if !crate::is_game_data_dir(path) {
+        bail!(
+          "'{}' doesn't look like a CrossCode game data directory",
+          path.display()
+        );
+      }
+      path.clone()
+    }
+    None => {
+      let mut installations = crate::autodetect_game_data_dirs();
+      match installations.len() {
+        0 => bail!(
+          "Couldn't autodetect the CrossCode game data directory, please specify it with --game-dir"
+        ),
+        1 => installations.pop().unwrap().path,
+        // picking one of them at random could mod the wrong copy of the game
+        _ => bail!(
+          "Found several CrossCode installations, please choose one with --game-dir:\n{}",
+          installations
+            .iter()
+            .map(|i| format!("  {}", i.describe()))
+            .collect::<Vec<String>>()
+            .join("\n")
+        ),
+      }
+    }
+  };
+  info!("game data dir = {}", game_data_dir.display());
+
+  let is_installed = game_data_dir.join(*CCLOADER_DIR_PATH).is_dir();
+  // e.g. after the game has been updated by Steam
+  let is_unpatched = is_installed
+    && matches!(installer::is_package_json_patched(&game_data_dir), Ok(false));
+  if is_unpatched
+    && matches!(
+      options.command,
+      Command::InstallMod
+        | Command::ListMods
+        | Command::UpdateMods
+        | Command::EnableMod
+        | Command::DisableMod
+    )
+  {
+    warn!(
+      "package.json doesn't start CCLoader anymore, probably because the game has been updated, use the 'repatch' command to fix that"
+    );
+  }
+
+  match options.command {
+    Command::Install | Command::Update => {
+      let mode = match (options.command, is_installed) {
+        (Command::Install, false) => InstallMode::FreshInstall,
+        (Command::Update, true) => InstallMode::Update,
+        (Command::Install, true) if is_unpatched => bail!(
+          "CCLoader is already installed in this game data directory, but the game doesn't start it anymore (probably because the game has been updated), use the 'repatch' command to fix that"
+        ),
+        (Command::Install, true) => bail!(
+          "CCLoader is already installed in this game data directory, use the 'update' command instead"
+        ),
+        _ => bail!(
+          "CCLoader isn't installed in this game data directory, use the 'install' command instead"
+        ),
+      };
+
+      let mut client = HttpClient::with_config(options.http_config.clone());
+      let mut release_info = fetch_release_info(options, &mut client)?;
+      if let Some(version) = &options.ccloader_version {
+        release_info.pin_version(&mut client, version)?;
+      }
+      info!("release info = {:?}", release_info);
+
+      match installer::check_game_compatibility(
+        &game_data_dir,
+        &release_info,
+        Rc::new(LogProgress::new()),
+      ) {
+        Compatibility::Compatible => {}
+        Compatibility::Unknown(reason) => {
+          warn!("couldn't check the compatibility with the game: {}", reason)
+        }
+        Compatibility::Incompatible(reason) => {
+          if !options.ignore_compatibility {
+            bail!(
+              "{}, pass --ignore-compatibility to install it anyway",
+              reason
+            );
+          }
+          warn!("{}, installing anyway", reason);
+        }
+      }
+
+      let question = if mode == InstallMode::Update {
+        let installed_version =
+          installer::detect_installed_ccloader_version(&game_data_dir);
+        info!("installed CCLoader version = {:?}", installed_version);
+        let is_newer = match &installed_version {
+          Some(installed_version) => installer::is_newer_version(
+            installed_version,
+            &release_info.version,
+          ),
+          None => true,
+        };
+        if options.ccloader_version.is_some() {
+          if installed_version.as_ref() == Some(&release_info.version) {
+            info!("CCLoader v{} is already installed", release_info.version);
+            return Ok(Outcome::Done);
+          }
+        } else if !is_newer {
+          info!(
+            "CCLoader v{} is already up to date",
+            installed_version.as_deref().unwrap_or("<unknown>")
+          );
+          return Ok(Outcome::Done);
+        }
+        format!(
+          "{} CCLoader from v{} to v{}?",
+          if is_newer { "Update" } else { "Downgrade" },
+          installed_version.as_deref().unwrap_or("<unknown>"),
+          release_info.version,
+        )
+      } else {
+        format!("Install CCLoader v{}?", release_info.version)
+      };
+      if !confirm(options, &question) {
+        return Ok(Outcome::Aborted);
+      }
+
+      installer::install_release(
+        &mut client,
+        &game_data_dir,
+        &release_info,
+        mode,
+        Rc::new(LogProgress::new()),
+      )?;
+      info!(
+        "CCLoader v{} has been successfully installed",
+        release_info.version
+      );
+    }
+
+    Command::Uninstall => {
+      if !is_installed {
+        bail!("CCLoader isn't installed in this game data directory");
+      }
+      if !confirm(options, "Uninstall CCLoader?") {
+        return Ok(Outcome::Aborted);
+      }
+
+      let manifest = installer::read_install_manifest(&game_data_dir);
+      let builtin_mods = installer::installed_builtin_mod_names(
+        &game_data_dir,
+        manifest.as_ref(),
+      );
+      let remove_builtin_mods = if options.remove_builtin_mods {
+        true
+      } else if builtin_mods.is_empty() || options.assume_yes {
+        false
+      } else {
+        confirm(
+          options,
+          &format!(
+            "Delete the built-in mods ({}) as well?",
+            builtin_mods.join(", ")
+          ),
+        )
+      };
+
+      installer::uninstall_ccloader(&game_data_dir, remove_builtin_mods)
+        .context("Couldn't uninstall CCLoader")?;
+      info!("CCLoader has been successfully uninstalled");
+    }
+
+    Command::Verify => {
+      let problems = installer::verify_installation(&game_data_dir)
+        .context("Couldn't verify the installation")?;
+      if !problems.is_empty() {
+        for problem in &problems {
+          error!("{}", problem);
+        }
+        info!("use the 'repair' command to fix the installation");
+        return Ok(Outcome::VerificationFailed);
+      }
+      info!("no problems found");
+    }
+
+    Command::Repatch => {
+      if !is_installed {
+        bail!(
+          "CCLoader isn't installed in this game data directory, use the 'install' command instead"
+        );
+      }
+      if !is_unpatched {
+        info!("package.json already starts CCLoader, nothing to do");
+        return Ok(Outcome::Done);
+      }
+      if !confirm(options, "Patch package.json to start CCLoader again?") {
+        return Ok(Outcome::Aborted);
+      }
+
+      installer::repatch_crosscode_assets(&game_data_dir)
+        .context("Couldn't patch package.json")?;
+      info!(
+        "package.json has been patched, the game will start CCLoader again"
+      );
+    }
+
+    Command::Repair => {
+      let installed_version =
+        match installer::detect_installed_ccloader_version(&game_data_dir) {
+          Some(version) => version,
+          None if !is_installed => {
+            bail!("CCLoader isn't installed in this game data directory")
+          }
+          None => bail!(
+            "Couldn't determine the installed CCLoader version, use the 'update' command to reinstall CCLoader"
+          ),
+        };
+      info!("installed CCLoader version = {}", installed_version);
+
+      // the files are compared with the release which is installed, not
+      // with the latest one
+      let mut client = HttpClient::with_config(options.http_config.clone());
+      let mut release_info = fetch_release_info(options, &mut client)?;
+      if release_info.version != installed_version {
+        if options.archive_path.is_some() {
+          bail!(
+            "The archive contains CCLoader v{}, but v{} is installed",
+            release_info.version,
+            installed_version
+          );
+        }
+        release_info.pin_version(&mut client, &installed_version)?;
+      }
+      info!("release info = {:?}", release_info);
+
+      if !confirm(
+        options,
+        &format!(
+          "Check the files of CCLoader v{} and restore the broken ones?",
+          installed_version
+        ),
+      ) {
+        return Ok(Outcome::Aborted);
+      }
+
+      let problems = installer::repair_installation(
+        &mut client,
+        &game_data_dir,
+        &release_info,
+        Rc::new(LogProgress::new()),
+      )
+      .context("Couldn't repair CCLoader")?;
+      if problems.is_empty() {
+        info!("no problems found");
+      } else {
+        info!("{} problems have been fixed", problems.len());
+      }
+    }
+
+    Command::InstallMod => {
+      if !is_installed {
+        warn!("CCLoader isn't installed, the mods won't be loaded without it");
+      }
+
+      if options
+        .mod_names
+        .iter()
+        .any(|name| name == ccmoddb::CCLOADER_PACKAGE_NAME)
+      {
+        bail!("CCLoader itself is installed with the 'install' command");
+      }
+
+      let mut client = HttpClient::with_config(options.http_config.clone());
+      let database = load_database(options, &mut client)?;
+      let progress = Rc::new(LogProgress::new());
+      let environment = Environment::detect(&game_data_dir, progress.clone())
+        .context("Couldn't inspect the game data directory")?;
+      info!("environment = {:?}", environment);
+      let steps = resolver::resolve(
+        &database,
+        &environment,
+        &options.mod_names,
+        options.ignore_compatibility,
+      )?;
+      if steps.is_empty() {
+        info!("nothing to install");
+        return Ok(Outcome::Done);
+      }
+
+      let question = format!(
+        "The following mods will be installed:\n{}\nContinue?",
+        describe_steps(&steps)
+      );
+      if !confirm(options, &question) {
+        return Ok(Outcome::Aborted);
+      }
+
+      // dependencies come first, so if one of the mods fails to install, the
+      // ones before it are still usable
+      for step in &steps {
+        let backup_path = mods::install_mod(
+          &mut client,
+          &game_data_dir,
+          &step.package,
+          &step.rel_path,
+          progress.clone(),
+        )?;
+        if let Some(backup_path) = backup_path {
+          info!(
+            "the previous version has been moved to {}",
+            backup_path.display()
+          );
+        }
+        info!(
+          "{} v{} has been successfully installed",
+          step.package.name, step.package.version
+        );
+      }
+    }
+
+    Command::UpdateMods => {
+      let mut client = HttpClient::with_config(options.http_config.clone());
+      let database = load_database(options, &mut client)?;
+      let progress = Rc::new(LogProgress::new());
+      let environment = Environment::detect(&game_data_dir, progress.clone())
+        .context("Couldn't inspect the game data directory")?;
+      info!("environment = {:?}", environment);
+      let records = manifest::read_mod_records(&game_data_dir)
+        .context("Couldn't read the mods manifest")?;
+      let builtin_mods = installer::installed_builtin_mod_names(
+        &game_data_dir,
+        installer::read_install_manifest(&game_data_dir).as_ref(),
+      );
+
+      // pairs of mod names and reasons
+      let mut skipped: Vec<(String, String)> = Vec::new();
+      let mut steps: Vec<Step> = Vec::new();
+      for installed_mod in &environment.installed_mods {
+        let name = &installed_mod.name;
+        // disabled built-in mods are updated together with CCLoader as well
+        if builtin_mods.contains(&installed_mod.dir_name) {
+          info!(
+            "{} is a built-in mod, it is updated together with CCLoader",
+            name
+          );
+          continue;
+        }
+        let package = match database.package(name) {
+          Some(package) => package,
+          None => {
+            skipped.push((name.clone(), "not in CCModDB".to_owned()));
+            continue;
+          }
+        };
+        let is_outdated = match &installed_mod.version {
+          Some(version) => {
+            installer::is_newer_version(version, &package.version)
+          }
+          None => true,
+        };
+        // the mod may have already been planned as a dependency of another one
+        if !is_outdated || steps.iter().any(|step| step.package.name == *name) {
+          continue;
+        }
+
+        match mods::is_locally_modified(&game_data_dir, installed_mod, &records)
+        {
+          Ok(false) => {}
+          Ok(true) => {
+            skipped.push((name.clone(), "locally modified".to_owned()));
+            continue;
+          }
+          Err(error) => {
+            skipped.push((
+              name.clone(),
+              format!("couldn't check for local modifications: {}", error),
+            ));
+            continue;
+          }
+        }
+
+        // every mod is resolved separately, so that a conflict blocks only
+        // the update of the affected mod
+        match resolver::resolve_updates(
+          &database,
+          &environment,
+          std::slice::from_ref(name),
+          options.ignore_compatibility,
+        ) {
+          Ok(mod_steps) => {
+            for step in mod_steps {
+              if !steps.iter().any(|s| s.package.name == step.package.name) {
+                steps.push(step);
+              }
+            }
+          }
+          Err(error) => skipped.push((name.clone(), error)),
+        }
+      }
+
+      for (name, reason) in &skipped {
+        warn!("skipping {}: {}", name, reason);
+      }
+      if steps.is_empty() {
+        info!("all mods are up to date");
+        return Ok(Outcome::Done);
+      }
+
+      let question = format!(
+        "The following mods will be updated or installed:\n{}\nContinue?",
+        describe_steps(&steps)
+      );
+      if !confirm(options, &question) {
+        return Ok(Outcome::Aborted);
+      }
+
+      // a failed mod doesn't stop the rest from being updated
+      let mut done = Vec::new();
+      let mut failed = Vec::new();
+      for step in &steps {
+        match mods::install_mod(
+          &mut client,
+          &game_data_dir,
+          &step.package,
+          &step.rel_path,
+          progress.clone(),
+        ) {
+          Ok(backup_path) => done.push((step, backup_path)),
+          Err(error) => {
+            error!("{}", error);
+            failed.push((step, error));
+          }
+        }
+      }
+
+      info!("summary:");
+      for (step, backup_path) in &done {
+        match (&step.installed_version, backup_path) {
+          (Some(installed_version), Some(backup_path)) => info!(
+            "  updated {} from v{} to v{}, the previous version is in {}",
+            step.package.name,
+            installed_version,
+            step.package.version,
+            backup_path.display()
+          ),
+          _ => {
+            info!("  installed {} v{}", step.package.name, step.package.version)
+          }
+        }
+      }
+      for (name, reason) in &skipped {
+        warn!("  skipped {}: {}", name, reason);
+      }
+      for (step, error) in &failed {
+        error!("  failed to update {}: {}", step.package.name, error);
+      }
+      if !failed.is_empty() {
+        bail!("{} of {} mods couldn't be updated", failed.len(), steps.len());
+      }
+    }
+
+    Command::ListMods => {
+      let installed_mods = mods::installed_mods(&game_data_dir)
+        .context("Couldn't list the installed mods")?;
+
+      // the list is still useful without the information about updates
+      let mut client = HttpClient::with_config(options.http_config.clone());
+      let database = match load_database(options, &mut client) {
+        Ok(database) => Some(database),
+        Err(error) => {
+          warn!("{}, updates won't be checked", error);
+          None
+        }
+      };
+
+      let mut rows = Vec::with_capacity(installed_mods.len());
+      let mut updates_count = 0;
+      for installed_mod in &installed_mods {
+        let latest_version: Option<String> = database
+          .as_ref()
+          .and_then(|database| database.package(&installed_mod.name))
+          .map(|package| package.version);
+        let update_available = match (&installed_mod.version, &latest_version) {
+          (Some(version), Some(latest_version)) => {
+            installer::is_newer_version(version, latest_version)
+          }
+          _ => false,
+        };
+        if update_available {
+          updates_count += 1;
+        }
+        rows.push((installed_mod, latest_version, update_available));
+      }
+
+      if options.json_output {
+        let data: Vec<JsonValue> = rows
+          .iter()
+          .map(|(installed_mod, latest_version, update_available)| {
+            json!({
+              "name": installed_mod.name,
+              "version": installed_mod.version,
+              "enabled": installed_mod.enabled,
+              "path": installed_mod.rel_path(),
+              "latest_version": latest_version,
+              "update_available": update_available,
+            })
+          })
+          .collect();
+        println!("{}", serde_json::to_string_pretty(&data).unwrap());
+      } else {
+        print_table(
+          &["NAME", "VERSION", "ENABLED", "LATEST", "PATH"],
+          rows
+            .iter()
+            .map(|(installed_mod, latest_version, update_available)| {
+              vec![
+                installed_mod.name.clone(),
+                installed_mod.version.clone().unwrap_or_else(|| "?".to_owned()),
+                if installed_mod.enabled { "yes" } else { "no" }.to_owned(),
+                match latest_version {
+                  Some(version) if *update_available => {
+                    format!("{} (update available)", version)
+                  }
+                  Some(version) => version.clone(),
+                  None => "-".to_owned(),
+                },
+                installed_mod.rel_path().display().to_string(),
+              ]
+            })
+            .collect(),
+        );
+      }
+      info!(
+        "{} mods installed, {} updates available",
+        installed_mods.len(),
+        updates_count
+      );
+    }
+
+    Command::EnableMod | Command::DisableMod => {
+      let enabled = options.command == Command::EnableMod;
+      let installed_mods = mods::installed_mods(&game_data_dir)
+        .context("Couldn't list the installed mods")?;
+      for name in &options.mod_names {
+        // the name of the directory is accepted too because that's what is
+        // visible in the file manager
+        let installed_mod = installed_mods
+          .iter()
+          .find(|m| m.name == *name)
+          .or_else(|| installed_mods.iter().find(|m| m.dir_name == *name));
+        let installed_mod = match installed_mod {
+          Some(installed_mod) => installed_mod,
+          None => bail!("{} isn't installed", name),
+        };
+        if installed_mod.enabled == enabled {
+          info!(
+            "{} is already {}",
+            name,
+            if enabled { "enabled" } else { "disabled" }
+          );
+          continue;
+        }
+        mods::set_mod_enabled(&game_data_dir, installed_mod, enabled)?;
+        info!(
+          "{} has been {}",
+          name,
+          if enabled { "enabled" } else { "disabled" }
+        );
+      }
+    }
+  }
+
+  Ok(Outcome::Done)
+}
+
+fn describe_steps(steps: &[Step]) -> String {
+  steps
+    .iter()
+    .map(|step| {
+      let mut line = match &step.installed_version {
+        Some(installed_version) => format!(
+          "  {} v{} (update from v{})",
+          step.package.name, step.package.version, installed_version
+        ),
+        None => format!("  {} v{}", step.package.name, step.package.version),
+      };
+      if !step.required_by.is_empty() {
+        line
+          .push_str(&format!(", required by {}", step.required_by.join(", ")));
+      }
+      line
+    })
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+// the last column isn't padded, so that lines don't end with spaces
+fn print_table(header: &[&str], rows: Vec<Vec<String>>) {
+  let mut widths: Vec<usize> =
+    header.iter().map(|s| s.chars().count()).collect();
+  for row in &rows {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.chars().count());
+    }
+  }
+
+  let header: Vec<String> = header.iter().map(|&s| s.to_owned()).collect();
+  for row in std::iter::once(&header).chain(&rows) {
+    let mut line = String::new();
+    for (index, (cell, width)) in row.iter().zip(&widths).enumerate() {
+      if index + 1 == row.len() {
+        line.push_str(cell);
+      } else {
+        line.push_str(&format!("{:width$}  ", cell, width = width));
+      }
+    }
+    println!("{}", line);
+  }
+}
+
+fn fetch_release_info(
+  options: &Options,
+  client: &mut HttpClient,
+) -> AppResult<ReleaseInfo> {
+  // without Internet access the database is used only when it has been given
+  // explicitly
+  if let (None, Some(archive_path)) =
+    (&options.database_sources, &options.archive_path)
+  {
+    return installer::release_info_from_archive_file_name(archive_path);
+  }
+
+  let mut release_info =
+    installer::fetch_latest_release_info(client, &database_sources(options)?)
+      .context("Couldn't fetch the latest release information")?;
+  if let Some(archive_path) = &options.archive_path {
+    release_info.use_local_archive(archive_path)?;
+  }
+  Ok(release_info)
+}
+
+fn load_database(
+  options: &Options,
+  client: &mut HttpClient,
+) -> AppResult<Database> {
+  Database::load(client, &database_sources(options)?)
+    .context("Couldn't load the CCModDB database")
+}
+
+// The databases given on the command line take precedence over the configured
+// ones, the official database is used when there are neither.
+fn database_sources(options: &Options) -> AppResult<Vec<Source>> {
+  let mut sources = match &options.database_sources {
+    Some(sources) => sources.clone(),
+    None => config::database_sources()?
+      .unwrap_or_else(|| vec![Source::Official(Channel::Stable)]),
+  };
+  if let Some(channel) = options.channel {
+    if !ccmoddb::switch_channel(&mut sources, channel) {
+      warn!("the official database isn't used, ignoring --channel");
+    }
+  }
+  info!("database sources = {:?}", sources);
+  Ok(sources)
+}
+
+fn confirm(options: &Options, question: &str) -> bool {
+  if options.assume_yes {
+    info!("{} yes (--yes was given)", question);
+    return true;
+  }
+
+  eprint!("{} [y/N] ", question);
+  // errors here mean that the terminal is unusable anyway
+  let _ = io::stderr().flush();
+  let mut answer = String::new();
+  if io::stdin().lock().read_line(&mut answer).is_err() {
+    return false;
+  }
+  matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,86 @@
+// Settings which can be given without command-line options, so that they apply
+// to the graphical installer too. The environment variable takes precedence
+// over the config file.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use log::info;
+use serde_json::Value as JsonValue;
+
+use crate::ccmoddb::Source;
+use crate::error::{Result as AppResult, ResultExt};
+
+// a comma-separated list of database sources, see `Source::parse`
+pub const DATABASES_ENV_VAR: &str = "CCLOADER_INSTALLER_DATABASES";
+
+const CONFIG_DIR_NAME: &str = "ccloader-installer";
+const CONFIG_FILE_NAME: &str = "config.json";
+
+// e.g. `~/.config/ccloader-installer/config.json` on GNU/Linux
+pub fn config_file_path() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+// Returns the configured database sources in the order of priority, or `None`
+// if neither the environment variable nor the config file lists any.
+pub fn database_sources() -> AppResult<Option<Vec<Source>>> {
+  if let Some(value) = env::var_os(DATABASES_ENV_VAR) {
+    let value = value.into_string().map_err(|_| {
+      format!("the value of {} is not valid Unicode", DATABASES_ENV_VAR)
+    })?;
+    let sources: Vec<Source> = value
+      .split(',')
+      .map(str::trim)
+      .filter(|s| !s.is_empty())
+      .map(Source::parse)
+      .collect();
+    if !sources.is_empty() {
+      info!("using the databases from {}", DATABASES_ENV_VAR);
+      return Ok(Some(sources));
+    }
+  }
+
+  let path = match config_file_path() {
+    Some(path) => path,
+    None => return Ok(None),
+  };
+  let bytes = match fs::read(&path) {
+    Ok(bytes) => bytes,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => {
+      return Err(e).with_context(|_| {
+        format!("couldn't read the config file '{}'", path.display())
+      })
+    }
+  };
+  let data: JsonValue = serde_json::from_slice(&bytes).with_context(|_| {
+    format!("couldn't parse the config file '{}'", path.display())
+  })?;
+
+  let databases = match &data["databases"] {
+    JsonValue::Null => return Ok(None),
+    JsonValue::Array(databases) => databases,
+    _ => bail!(
+      "'databases' in the config file '{}' must be a list",
+      path.display()
+    ),
+  };
+  let mut sources = Vec::with_capacity(databases.len());
+  for database in databases {
+    match database.as_str() {
+      Some(text) => sources.push(Source::parse(text)),
+      None => bail!(
+        "'databases' in the config file '{}' must contain only strings",
+        path.display()
+      ),
+    }
+  }
+  if sources.is_empty() {
+    return Ok(None);
+  }
+  info!("using the databases from {}", path.display());
+  Ok(Some(sources))
+}
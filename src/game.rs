@@ -0,0 +1,263 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use log::{info, warn};
+use semver::{Prerelease, Version, VersionReq};
+use serde_json::Value as JsonValue;
+
+use crate::progress::{Progress, ProgressReader};
+
+const CHANGELOG_FILE_PATH: &str = "assets/data/changelog.json";
+
+// used when CCModDB doesn't list any requirements for the game version, CCLoader
+// has never supported the demo builds, which are versioned 0.x
+const DEFAULT_GAME_VERSION_REQ: &str = ">=1.0.0";
+
+// The version of the game isn't stored in `package.json` (it always says
+// 1.0.0), but the newest entry of the changelog shown in the game's menu has
+// it. CCLoader determines the version the same way.
+pub fn detect_game_version(game_data_dir: &Path) -> Option<String> {
+  let bytes = fs::read(game_data_dir.join(CHANGELOG_FILE_PATH)).ok()?;
+  let data: JsonValue = serde_json::from_slice(&bytes).ok()?;
+  data["changelog"][0]["version"].as_str().map(|s| s.to_owned())
+}
+
+// nw.js doesn't put its version into any separate file, so the runtime binary
+// is searched for strings such as `nwjs/0.44.5`. This is a heuristic, `None` is
+// returned when it doesn't work out. The binaries are huge, so only their
+// beginnings are searched.
+pub fn detect_nwjs_version(
+  game_data_dir: &Path,
+  progress: Rc<dyn Progress>,
+) -> Option<String> {
+  for path in nwjs_binary_paths(game_data_dir) {
+    if !path.is_file() {
+      continue;
+    }
+    info!("looking for the nw.js version in {}", path.display());
+    progress.set_status("Detecting the version of nw.js...");
+    progress.set_fraction(Some(0.0));
+    match find_nwjs_version_in_file(&path, progress.clone()) {
+      Ok(Some(version)) => return Some(version),
+      Ok(None) => {}
+      Err(error) => warn!("couldn't read {}: {}", path.display(), error),
+    }
+    if progress.is_cancelled() {
+      return None;
+    }
+  }
+  None
+}
+
+#[cfg(target_os = "macos")]
+fn nwjs_binary_paths(game_data_dir: &Path) -> Vec<PathBuf> {
+  // the game data directory is `CrossCode.app/Contents/Resources/app.nw`
+  let contents_dir = game_data_dir.join("../..");
+  vec![contents_dir.join("Frameworks/nwjs Framework.framework/nwjs Framework")]
+}
+
+#[cfg(target_os = "windows")]
+fn nwjs_binary_paths(game_data_dir: &Path) -> Vec<PathBuf> {
+  vec![game_data_dir.join("nw.dll"), game_data_dir.join("CrossCode.exe")]
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn nwjs_binary_paths(game_data_dir: &Path) -> Vec<PathBuf> {
+  vec![
+    game_data_dir.join("lib/libnw.so"),
+    game_data_dir.join("CrossCode"),
+    game_data_dir.join("nw"),
+  ]
+}
+
+// binaries without a version string would otherwise be read to the end, which
+// takes a while for the 100+ MB ones
+const MAX_NWJS_SCANNED_BYTES: u64 = 64 * 1024 * 1024;
+
+fn find_nwjs_version_in_file(
+  path: &Path,
+  progress: Rc<dyn Progress>,
+) -> io::Result<Option<String>> {
+  let file = File::open(path)?;
+  let total = file.metadata()?.len().min(MAX_NWJS_SCANNED_BYTES);
+  find_nwjs_version(ProgressReader::new(file, total, progress))
+}
+
+fn find_nwjs_version<R: Read>(reader: R) -> io::Result<Option<String>> {
+  // enough to not lose a marker together with the version at a chunk border
+  const OVERLAP: usize = 32;
+
+  let mut reader = reader.take(MAX_NWJS_SCANNED_BYTES);
+  let mut buf = vec![0; 1024 * 1024];
+  let mut len = 0;
+  loop {
+    let read = reader.read(&mut buf[len..])?;
+    if read == 0 {
+      return Ok(None);
+    }
+    len += read;
+
+    if let Some(version) = find_version_after_marker(&buf[..len]) {
+      return Ok(Some(version));
+    }
+
+    if len > OVERLAP {
+      buf.copy_within(len - OVERLAP..len, 0);
+      len = OVERLAP;
+    }
+  }
+}
+
+// all markers end with a slash, so the chunk is scanned only once for slashes
+// instead of once per marker
+fn find_version_after_marker(chunk: &[u8]) -> Option<String> {
+  const MARKERS: &[&[u8]] = &[b"nwjs/", b"NWjs/", b"node-webkit/"];
+
+  chunk
+    .iter()
+    .enumerate()
+    .filter(|&(_, &b)| b == b'/')
+    .filter(|&(index, _)| {
+      MARKERS.iter().any(|marker| chunk[..=index].ends_with(marker))
+    })
+    .find_map(|(index, _)| parse_version_prefix(&chunk[index + 1..]))
+}
+
+// accepts only complete `X.Y.Z` versions, so that other uses of the markers
+// in the binary (e.g. in URLs) are skipped, as well as versions cut off by the
+// end of the buffer
+fn parse_version_prefix(bytes: &[u8]) -> Option<String> {
+  let len = bytes.iter().position(|&b| !(b.is_ascii_digit() || b == b'.'))?;
+  let text = std::str::from_utf8(&bytes[..len]).ok()?;
+  Version::parse(text).ok()?;
+  Some(text.to_owned())
+}
+
+// The game marks hotfixes with a suffix, e.g. `1.4.2-3`, which would make it
+// a pre-release for semver, and pre-releases don't match most requirements, so
+// the suffix is dropped.
+pub fn parse_game_version(version: &str) -> Result<Version, semver::Error> {
+  Version::parse(version).map(|mut parsed| {
+    parsed.pre = Prerelease::EMPTY;
+    parsed
+  })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compatibility {
+  Compatible,
+  // the check couldn't be done, the installation may still work
+  Unknown(String),
+  Incompatible(String),
+}
+
+// Checks the versions of the game and of nw.js against the requirements of a
+// CCLoader release. An nw.js version or requirement which couldn't be
+// determined is skipped because they are rarely known.
+pub fn check_compatibility(
+  game_version: Option<&str>,
+  nwjs_version: Option<&str>,
+  game_version_req: Option<&str>,
+  nwjs_version_req: Option<&str>,
+) -> Compatibility {
+  let game_version_req = game_version_req.unwrap_or(DEFAULT_GAME_VERSION_REQ);
+  match check_version("CrossCode", game_version, game_version_req) {
+    Compatibility::Compatible => {}
+    other => return other,
+  }
+
+  match (nwjs_version, nwjs_version_req) {
+    (Some(version), Some(req)) => check_version("nw.js", Some(version), req),
+    _ => Compatibility::Compatible,
+  }
+}
+
+fn check_version(
+  name: &str,
+  version: Option<&str>,
+  req: &str,
+) -> Compatibility {
+  let req = match VersionReq::parse(req) {
+    Ok(req) => req,
+    Err(_) => {
+      return Compatibility::Unknown(format!(
+        "the required {} version '{}' isn't valid",
+        name, req
+      ))
+    }
+  };
+  let version = match version {
+    Some(version) => version,
+    None => {
+      return Compatibility::Unknown(format!(
+        "couldn't determine the {} version",
+        name
+      ))
+    }
+  };
+  match parse_game_version(version) {
+    Ok(parsed) if req.matches(&parsed) => Compatibility::Compatible,
+    Ok(_) => Compatibility::Incompatible(format!(
+      "{} {} isn't supported by this CCLoader release, it requires {}",
+      name, version, req
+    )),
+    Err(_) => Compatibility::Unknown(format!(
+      "the {} version '{}' isn't a valid version",
+      name, version
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{self, Cursor, Read};
+
+  #[test]
+  fn finds_the_version_after_any_marker() {
+    for &data in &[
+      &b"\0\0Mozilla/5.0 nwjs/0.44.5 Chrome\0"[..],
+      b"\0NWjs/0.35.5\0",
+      b"\0node-webkit/0.12.3\0",
+    ] {
+      assert!(find_nwjs_version(Cursor::new(data)).unwrap().is_some());
+    }
+    assert_eq!(
+      find_nwjs_version(Cursor::new(&b"\0nwjs/0.44.5\0"[..])).unwrap(),
+      Some("0.44.5".to_owned())
+    );
+  }
+
+  #[test]
+  fn skips_markers_without_a_complete_version() {
+    let data = b"https://nwjs/docs nwjs/0.44 nwjs/ nwjs/0.44.6\0";
+    assert_eq!(
+      find_nwjs_version(Cursor::new(&data[..])).unwrap(),
+      Some("0.44.6".to_owned())
+    );
+    assert_eq!(
+      find_nwjs_version(Cursor::new(&b"nwjs/1.2.3"[..])).unwrap(),
+      None
+    );
+  }
+
+  #[test]
+  fn finds_markers_across_chunk_borders() {
+    let mut data = vec![0; 1024 * 1024 - 3];
+    data.extend_from_slice(b"nwjs/0.44.5\0");
+    assert_eq!(
+      find_nwjs_version(Cursor::new(data)).unwrap(),
+      Some("0.44.5".to_owned())
+    );
+  }
+
+  #[test]
+  fn stops_after_the_scan_limit() {
+    let data = io::repeat(0)
+      .take(MAX_NWJS_SCANNED_BYTES)
+      .chain(Cursor::new(&b"nwjs/0.44.5\0"[..]));
+    assert_eq!(find_nwjs_version(data).unwrap(), None);
+  }
+}
@@ -1,6 +1,13 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use ::curl::easy as curl;
 use ::curl::Error as CurlError;
-use log::info;
+use log::{info, warn};
 
 pub use http::header::{self, HeaderMap, HeaderName, HeaderValue};
 pub use http::{Method, StatusCode, Uri, Version};
@@ -13,17 +20,219 @@ pub type Body = Vec<u8>;
 pub type Request<T = Body> = http::Request<T>;
 pub type Response<T = Body> = http::Response<T>;
 
+// Receives the number of bytes downloaded so far and the total size of the
+// response body if it is known. Returning `false` cancels the transfer.
+pub type ProgressCallback = Box<dyn FnMut(u64, Option<u64>) -> bool>;
+
+#[derive(Debug)]
+pub enum Error {
+  Curl(CurlError),
+  Io(io::Error),
+  Cancelled,
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Curl(e) => e.fmt(f),
+      Error::Io(e) => write!(f, "couldn't write the response body: {}", e),
+      Error::Cancelled => write!(f, "the transfer was cancelled"),
+    }
+  }
+}
+
+impl From<CurlError> for Error {
+  fn from(error: CurlError) -> Self {
+    if error.is_aborted_by_callback() {
+      Error::Cancelled
+    } else {
+      Error::Curl(error)
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+  pub connect_timeout: Duration,
+  // a transfer is aborted if it stays slower than `low_speed_limit` bytes per
+  // second for `low_speed_time`, which catches stalled connections
+  pub low_speed_limit: u32,
+  pub low_speed_time: Duration,
+  pub max_retries: u32,
+  // doubled after every failed attempt
+  pub retry_delay: Duration,
+}
+
+impl Default for HttpClientConfig {
+  fn default() -> Self {
+    Self {
+      connect_timeout: Duration::from_secs(30),
+      low_speed_limit: 1,
+      low_speed_time: Duration::from_secs(60),
+      max_retries: 3,
+      retry_delay: Duration::from_secs(1),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct HttpClient {
   curl: curl::Easy2<Handler>,
+  config: HttpClientConfig,
 }
 
 impl HttpClient {
   pub fn new() -> Self {
-    HttpClient { curl: curl::Easy2::new(Handler::new()) }
+    Self::with_config(HttpClientConfig::default())
+  }
+
+  pub fn with_config(config: HttpClientConfig) -> Self {
+    HttpClient { curl: curl::Easy2::new(Handler::new()), config }
+  }
+
+  // The callback is used for all following requests until it is replaced or
+  // removed by passing `None`.
+  pub fn set_progress_callback(&mut self, callback: Option<ProgressCallback>) {
+    self.curl.get_mut().progress_callback = callback;
+  }
+
+  pub fn send(&mut self, request: Request) -> Result<Response, Error> {
+    self.with_retries(|this| {
+      let response = this.perform(&request)?;
+      let handler: &mut Handler = this.curl.get_mut();
+      let body = handler.response_body.take().unwrap_or_default();
+      Ok(response.map(|()| body))
+    })
+  }
+
+  // Writes the response body straight into the file at `path` as it arrives
+  // instead of buffering it in memory, which is meant for downloading large
+  // files. If the file already contains the beginning of the body, e.g. from
+  // an interrupted attempt, only the rest of it is requested, provided that
+  // the server confirms with `If-Range` that the file hasn't changed since.
+  pub fn send_to_file(
+    &mut self,
+    request: Request,
+    path: &Path,
+  ) -> Result<Response<()>, Error> {
+    let validator_path = validator_path(path);
+    let mut restarted = false;
+    self.with_retries(|this| loop {
+      let file: File = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(Error::Io)?;
+      let mut offset: u64 = file.metadata().map_err(Error::Io)?.len();
+      let mut validator: Option<String> = None;
+      if offset > 0 {
+        validator = fs::read_to_string(&validator_path).ok();
+        if validator.is_some() {
+          info!("resuming the download from byte {}", offset);
+        } else {
+          // without a validator there is no way to tell whether the file on
+          // the server is still the same
+          warn!("the partial download can't be checked, starting over");
+          file.set_len(0).map_err(Error::Io)?;
+          offset = 0;
+        }
+      }
+
+      let handler: &mut Handler = this.curl.get_mut();
+      handler.response_body_file = Some(file);
+      handler.response_body_validator_path = Some(validator_path.clone());
+      handler.response_body_started = false;
+      handler.resume_offset = offset;
+      handler.resume_validator = validator;
+      let result = this.perform(&request);
+      let handler: &mut Handler = this.curl.get_mut();
+      handler.response_body_file = None;
+      handler.response_body_validator_path = None;
+      handler.resume_offset = 0;
+      handler.resume_validator = None;
+      if let Some(error) = handler.response_body_file_error.take() {
+        return Err(Error::Io(error));
+      }
+
+      // the file is complete, there is nothing to resume anymore
+      if matches!(&result, Ok(response) if response.status().is_success()) {
+        if let Err(e) = fs::remove_file(&validator_path) {
+          if e.kind() != io::ErrorKind::NotFound {
+            warn!("couldn't delete '{}': {}", validator_path.display(), e);
+          }
+        }
+      }
+
+      // the partially downloaded file is probably larger than what the
+      // server has, the only option left is to start over
+      let cant_resume = match &result {
+        Ok(response) => response.status() == StatusCode::RANGE_NOT_SATISFIABLE,
+        Err(_) => false,
+      };
+      if offset > 0 && cant_resume && !restarted {
+        warn!("the download can't be resumed, starting over");
+        restarted = true;
+        File::create(path).map_err(Error::Io)?;
+        continue;
+      }
+
+      return result;
+    })
   }
 
-  pub fn send(&mut self, request: Request) -> Result<Response, CurlError> {
+  // Repeats the request on network errors which are likely to go away by
+  // themselves and on server errors, waiting longer after each attempt.
+  fn with_retries<T, F>(&mut self, mut attempt: F) -> Result<Response<T>, Error>
+  where
+    F: FnMut(&mut Self) -> Result<Response<T>, Error>,
+  {
+    let mut retries: u32 = 0;
+    let mut delay: Duration = self.config.retry_delay;
+    loop {
+      let result = attempt(self);
+      let failure: Option<String> = match &result {
+        Ok(response) if response.status().is_server_error() => {
+          Some(format!("HTTP error: {}", response.status()))
+        }
+        Err(Error::Curl(error)) if is_transient_curl_error(error) => {
+          Some(error.to_string())
+        }
+        _ => None,
+      };
+
+      let failure = match failure {
+        Some(failure) if retries < self.config.max_retries => failure,
+        _ => return result,
+      };
+      retries += 1;
+      warn!(
+        "request failed ({}), retrying in {:?} ({}/{})",
+        failure, delay, retries, self.config.max_retries
+      );
+      self.sleep(delay)?;
+      delay *= 2;
+    }
+  }
+
+  // The progress callback keeps being called while waiting, so that the UI
+  // stays responsive and the user can cancel the operation.
+  fn sleep(&mut self, duration: Duration) -> Result<(), Error> {
+    let deadline = Instant::now() + duration;
+    loop {
+      if let Some(callback) = self.curl.get_mut().progress_callback.as_mut() {
+        if !callback(0, None) {
+          return Err(Error::Cancelled);
+        }
+      }
+      let now = Instant::now();
+      if now >= deadline {
+        return Ok(());
+      }
+      thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+  }
+
+  fn perform(&mut self, request: &Request) -> Result<Response<()>, Error> {
     info!(
       "request: {} {} {:?} {:?}",
       request.method(),
@@ -34,13 +243,12 @@ impl HttpClient {
 
     self.curl.reset();
     self.configure_curl_default()?;
-    self.configure_curl_for_request(&request)?;
+    self.configure_curl_for_request(request)?;
     self.curl.perform()?;
 
     let handler: &mut Handler = self.curl.get_mut();
 
-    let mut response =
-      Response::new(handler.response_body.take().unwrap_or_default());
+    let mut response = Response::new(());
     if let Some(version) = handler.response_version.take() {
       *response.version_mut() = version;
     }
@@ -68,6 +276,18 @@ impl HttpClient {
 
   fn configure_curl_default(&mut self) -> Result<(), CurlError> {
     self.curl.follow_location(true)?;
+    self.curl.connect_timeout(self.config.connect_timeout)?;
+    self.curl.low_speed_limit(self.config.low_speed_limit)?;
+    self.curl.low_speed_time(self.config.low_speed_time)?;
+    let handler: &Handler = self.curl.get_ref();
+    let has_progress_callback = handler.progress_callback.is_some();
+    let resume_offset = handler.resume_offset;
+    self.curl.progress(has_progress_callback)?;
+    if resume_offset > 0 {
+      // unlike `resume_from`, this doesn't make curl fail when the server
+      // ignores the range, that case is handled in `Handler::write`
+      self.curl.range(&format!("{}-", resume_offset))?;
+    }
     // self.curl.fail_on_error(true)?;
     self.curl.useragent(&format!(
       "{}/{} (by @dmitmel)",
@@ -134,6 +354,14 @@ impl HttpClient {
       let header = format!("{}: {}", name.as_str(), value.to_str().unwrap());
       headers.append(&header)?;
     }
+    let handler: &Handler = self.curl.get_ref();
+    if handler.resume_offset > 0 {
+      if let Some(validator) = &handler.resume_validator {
+        // the server sends the whole body instead of the range if the file
+        // has changed
+        headers.append(&format!("{}: {}", header::IF_RANGE, validator))?;
+      }
+    }
     self.curl.http_headers(headers)?;
 
     if has_body {
@@ -151,12 +379,20 @@ impl HttpClient {
   }
 }
 
-#[derive(Debug)]
 struct Handler {
   response_version: Option<Version>,
   response_status_code: Option<StatusCode>,
   response_headers: Option<HeaderMap>,
   response_body: Option<Vec<u8>>,
+  response_body_file: Option<File>,
+  response_body_file_error: Option<io::Error>,
+  // where the validator of the response written into `response_body_file`
+  // is saved for resuming the download later
+  response_body_validator_path: Option<PathBuf>,
+  response_body_started: bool,
+  resume_offset: u64,
+  resume_validator: Option<String>,
+  progress_callback: Option<ProgressCallback>,
 }
 
 impl Handler {
@@ -166,10 +402,62 @@ impl Handler {
       response_status_code: None,
       response_headers: None,
       response_body: None,
+      response_body_file: None,
+      response_body_file_error: None,
+      response_body_validator_path: None,
+      response_body_started: false,
+      resume_offset: 0,
+      resume_validator: None,
+      progress_callback: None,
+    }
+  }
+
+  // `If-Range` accepts either a strong `ETag` or `Last-Modified`, the file is
+  // deleted when the response has neither, so that it isn't resumed
+  fn write_response_body_validator(&self) -> io::Result<()> {
+    let path = match &self.response_body_validator_path {
+      Some(path) => path,
+      None => return Ok(()),
+    };
+    let header_value = |name: HeaderName| {
+      self
+        .response_headers
+        .as_ref()
+        .and_then(|headers| headers.get(name))
+        .and_then(|value| value.to_str().ok())
+    };
+    let validator = header_value(header::ETAG)
+      .filter(|etag| !etag.starts_with("W/"))
+      .or_else(|| header_value(header::LAST_MODIFIED));
+    match validator {
+      Some(validator) => fs::write(path, validator),
+      None => match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+      },
     }
   }
 }
 
+// implemented by hand because closures don't implement `Debug`
+impl fmt::Debug for Handler {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Handler")
+      .field("response_version", &self.response_version)
+      .field("response_status_code", &self.response_status_code)
+      .field("response_headers", &self.response_headers)
+      .field("response_body", &self.response_body)
+      .field("response_body_file", &self.response_body_file)
+      .field("response_body_file_error", &self.response_body_file_error)
+      .field("response_body_validator_path", &self.response_body_validator_path)
+      .field("response_body_started", &self.response_body_started)
+      .field("resume_offset", &self.resume_offset)
+      .field("resume_validator", &self.resume_validator)
+      .field("progress_callback", &self.progress_callback.is_some())
+      .finish()
+  }
+}
+
 impl curl::Handler for Handler {
   fn header(&mut self, data: &[u8]) -> bool {
     // this part was influenced by https://github.com/sagebind/isahc/blob/969b0800b5ab9119e2f72532a7522247bc639c2f/src/handler.rs
@@ -190,7 +478,62 @@ impl curl::Handler for Handler {
     true
   }
 
+  fn progress(
+    &mut self,
+    dltotal: f64,
+    dlnow: f64,
+    _ultotal: f64,
+    _ulnow: f64,
+  ) -> bool {
+    // curl counts only the bytes which are transferred in this request, so
+    // the already downloaded part has to be added when resuming
+    let offset = self.resume_offset;
+    match self.progress_callback.as_mut() {
+      // curl reports zero as the total size until it becomes known
+      Some(callback) => callback(
+        offset + dlnow as u64,
+        if dltotal > 0.0 { Some(offset + dltotal as u64) } else { None },
+      ),
+      None => true,
+    }
+  }
+
   fn write(&mut self, chunk: &[u8]) -> Result<usize, curl::WriteError> {
+    if let Some(file) = self.response_body_file.as_mut() {
+      // error pages are thrown away, so that the file keeps only the real
+      // data, which can be resumed later
+      let status = self.response_status_code;
+      if !status.is_some_and(|s| s.is_success()) {
+        return Ok(chunk.len());
+      }
+      if self.resume_offset > 0 && status != Some(StatusCode::PARTIAL_CONTENT) {
+        // the server has ignored the range or the file has changed, either
+        // way the whole body is sent
+        info!("the download can't be resumed, starting over");
+        self.resume_offset = 0;
+        if let Err(error) = file.set_len(0) {
+          self.response_body_file_error = Some(error);
+          return Ok(0);
+        }
+      }
+      if !self.response_body_started {
+        self.response_body_started = true;
+        if let Err(error) = self.write_response_body_validator() {
+          self.response_body_file_error = Some(error);
+          return Ok(0);
+        }
+      }
+      let file = self.response_body_file.as_mut().unwrap();
+      return match file.write_all(chunk) {
+        Ok(()) => Ok(chunk.len()),
+        Err(error) => {
+          self.response_body_file_error = Some(error);
+          // reporting fewer bytes than were given aborts the transfer
+          Ok(0)
+        }
+      };
+    }
+
     if self.response_body.is_none() {
       if let Some(headers) = self.response_headers.as_ref() {
         self.response_body = Some(Vec::with_capacity(
@@ -205,6 +548,12 @@ impl curl::Handler for Handler {
   }
 }
 
+fn validator_path(path: &Path) -> PathBuf {
+  let mut file_name = path.file_name().unwrap_or_default().to_owned();
+  file_name.push(".validator");
+  path.with_file_name(file_name)
+}
+
 fn try_get_content_length_from_headers(headers: &HeaderMap) -> Option<usize> {
   if let Some(header_value) = headers.get(header::TRANSFER_ENCODING) {
     if header_value.as_bytes().to_ascii_lowercase() != b"identity" {
@@ -215,3 +564,17 @@ fn try_get_content_length_from_headers(headers: &HeaderMap) -> Option<usize> {
   let header_value = headers.get(header::CONTENT_LENGTH)?;
   ascii_to_int(header_value.as_bytes())
 }
+
+fn is_transient_curl_error(error: &CurlError) -> bool {
+  error.is_couldnt_resolve_proxy()
+    || error.is_couldnt_resolve_host()
+    || error.is_couldnt_connect()
+    || error.is_partial_file()
+    || error.is_operation_timedout()
+    || error.is_ssl_connect_error()
+    || error.is_got_nothing()
+    || error.is_send_error()
+    || error.is_recv_error()
+    || error.is_http2_error()
+    || error.is_http2_stream_error()
+}
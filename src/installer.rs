@@ -0,0 +1,1333 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use semver::Version;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+
+use crate::archive::{self, ArchiveFormat};
+use crate::cache;
+use crate::ccmoddb::{self, Database, Source};
+use crate::error::{err_msg, Result as AppResult, ResultExt};
+use crate::game::{self, Compatibility};
+use crate::http_client::{HttpClient, Request as HttpRequest, Uri};
+use crate::manifest::{self, InstallManifest};
+use crate::mods::DISABLED_MODS_DIR_PATH;
+use crate::progress::Progress;
+use crate::transaction::Transaction;
+
+pub const PACKAGE_JSON_FILE_NAME: &str = "package.json";
+
+// CCModDB lists only the latest release, the older ones are looked up on GitHub
+pub const CCLOADER_RELEASES_URL: &str =
+  "https://api.github.com/repos/CCDirectLink/CCLoader/releases?per_page=100";
+
+// the value of `main` in `package.json` of an unmodded game
+const ORIGINAL_PACKAGE_JSON_MAIN: &str = "assets/node-webkit.html";
+
+// mods which are distributed together with CCLoader
+const BUILTIN_MOD_NAMES: &[&str] =
+  &["simplify", "ccloader-version-display", "openDevTools"];
+
+lazy_static! {
+  pub static ref CCLOADER_DIR_PATH: &'static Path = Path::new("ccloader");
+  pub static ref MODS_DIR_PATH: &'static Path = Path::new("assets/mods");
+}
+
+pub fn install_release(
+  client: &mut HttpClient,
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+  mode: InstallMode,
+  progress: Rc<dyn Progress>,
+) -> AppResult<()> {
+  let download_url: &Uri = match &release_info.source {
+    ReleaseSource::Remote(url) => url,
+    ReleaseSource::Local(archive_path) => {
+      return verify_archive(archive_path, release_info.sha256.as_deref())
+        .and_then(|()| {
+          install_release_archive(
+            game_data_dir,
+            release_info,
+            archive_path,
+            mode,
+            progress,
+          )
+        });
+    }
+  };
+
+  progress
+    .set_status(&format!("Downloading CCLoader v{}...", release_info.version));
+  let archive = download_archive(
+    client,
+    download_url,
+    release_info.sha256.as_deref(),
+    progress.clone(),
+  )
+  .with_context(|_| {
+    format!("Couldn't download CCLoader v{}", release_info.version)
+  })?;
+
+  let result = install_release_archive(
+    game_data_dir,
+    release_info,
+    &archive.path,
+    mode,
+    progress,
+  );
+
+  remove_downloaded_archive(&archive);
+
+  result
+}
+
+pub fn install_release_archive(
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+  archive_path: &Path,
+  mode: InstallMode,
+  progress: Rc<dyn Progress>,
+) -> AppResult<()> {
+  let previous_manifest = read_install_manifest(game_data_dir);
+
+  // nothing in the game data directory is modified until this point, from now
+  // on every change is rolled back if any of the following steps fails
+  let mut transaction = Transaction::begin(game_data_dir)
+    .context("Couldn't prepare the installation")?;
+
+  progress
+    .set_status(&format!("Installing CCLoader v{}...", release_info.version));
+  progress.set_fraction(Some(0.0));
+  let installed_builtin_mods = unpack_release_archive(
+    archive_path,
+    &release_info.root_dir_path,
+    game_data_dir,
+    mode,
+    &mut transaction,
+    progress,
+  )
+  .context("Couldn't unpack the CCLoader release archive")?;
+
+  let previous_main = patch_crosscode_assets(game_data_dir, &mut transaction)
+    .context("Couldn't patch CrossCode assets")?;
+
+  transaction
+    .snapshot_file(Path::new(manifest::MANIFEST_FILE_NAME))
+    .and_then(|()| {
+      write_install_manifest(
+        game_data_dir,
+        release_info,
+        previous_manifest,
+        previous_main,
+        installed_builtin_mods,
+      )
+    })
+    .context("Couldn't record the installation manifest")?;
+
+  transaction.commit();
+
+  Ok(())
+}
+
+// Checks that CCLoader in the given game data directory is in a working
+// state, returns the list of found problems.
+pub fn verify_installation(game_data_dir: &Path) -> AppResult<Vec<String>> {
+  let mut problems = Vec::new();
+
+  if !game_data_dir.join(*CCLOADER_DIR_PATH).is_dir() {
+    problems
+      .push(format!("directory '{}' is missing", CCLOADER_DIR_PATH.display()));
+  }
+
+  let expected_main = ccloader_package_json_main();
+  match read_package_json_main(game_data_dir)?.as_deref() {
+    Some(main) if main == expected_main => {}
+    main => problems.push(format!(
+      "main in package.json is {:?} instead of {:?}",
+      main, expected_main
+    )),
+  }
+
+  match InstallManifest::read(game_data_dir)? {
+    Some(manifest) => {
+      if manifest.checksums.is_empty() {
+        warn!("the installation manifest doesn't record checksums, only missing files can be detected");
+      }
+      for file in &manifest.files {
+        let path = game_data_dir.join(file);
+        if !path.is_file() {
+          problems.push(format!("file '{}' is missing", file));
+          continue;
+        }
+        if let Some(expected) = manifest.checksums.get(file) {
+          let actual = sha256_file(&path)
+            .with_context(|_| format!("couldn't read '{}'", file))?;
+          if actual != *expected {
+            problems.push(format!("file '{}' has been changed", file));
+          }
+        }
+      }
+    }
+    None => warn!(
+      "installation manifest not found, the installed files can't be checked"
+    ),
+  }
+
+  Ok(problems)
+}
+
+// Compares the installed files of CCLoader and of the built-in mods with the
+// release archive and puts back the ones which are missing or have been
+// changed, then patches `package.json` again if needed. Returns the list of
+// fixed problems.
+pub fn repair_installation(
+  client: &mut HttpClient,
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+  progress: Rc<dyn Progress>,
+) -> AppResult<Vec<String>> {
+  let download_url: &Uri = match &release_info.source {
+    ReleaseSource::Remote(url) => url,
+    ReleaseSource::Local(archive_path) => {
+      return verify_archive(archive_path, release_info.sha256.as_deref())
+        .and_then(|()| {
+          repair_installation_from_archive(
+            game_data_dir,
+            release_info,
+            archive_path,
+            progress,
+          )
+        });
+    }
+  };
+
+  progress
+    .set_status(&format!("Downloading CCLoader v{}...", release_info.version));
+  let archive = download_archive(
+    client,
+    download_url,
+    release_info.sha256.as_deref(),
+    progress.clone(),
+  )
+  .with_context(|_| {
+    format!("Couldn't download CCLoader v{}", release_info.version)
+  })?;
+
+  let result = repair_installation_from_archive(
+    game_data_dir,
+    release_info,
+    &archive.path,
+    progress,
+  );
+
+  remove_downloaded_archive(&archive);
+
+  result
+}
+
+fn repair_installation_from_archive(
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+  archive_path: &Path,
+  progress: Rc<dyn Progress>,
+) -> AppResult<Vec<String>> {
+  let previous_manifest = read_install_manifest(game_data_dir);
+  // built-in mods which have been deleted on purpose aren't in the manifest
+  let builtin_mods: Vec<String> = match &previous_manifest {
+    Some(manifest) => manifest.builtin_mods.clone(),
+    None => installed_builtin_mod_names(game_data_dir, None),
+  };
+
+  let mut transaction =
+    Transaction::begin(game_data_dir).context("Couldn't prepare the repair")?;
+
+  progress.set_status(&format!(
+    "Checking the files of CCLoader v{}...",
+    release_info.version
+  ));
+  progress.set_fraction(Some(0.0));
+  let unpack_dir = transaction.staging_dir().join("unpacked");
+  info!("unpacking the release archive to {}", unpack_dir.display());
+  let root_dir_path = &release_info.root_dir_path;
+  archive::unpack_archive(
+    archive_path,
+    &unpack_dir,
+    progress.clone(),
+    &|entry_path| match entry_path.strip_prefix(root_dir_path) {
+      Ok(rel_path) => {
+        rel_path.starts_with(*CCLOADER_DIR_PATH)
+          || rel_path.starts_with(*MODS_DIR_PATH)
+      }
+      Err(_) => false,
+    },
+  )
+  .context("Couldn't unpack the CCLoader release archive")?;
+
+  if progress.is_cancelled() {
+    bail!("cancelled by the user");
+  }
+
+  let unpacked_release_dir = unpack_dir.join(root_dir_path);
+  // pairs of a directory in the archive and its location in the game, which
+  // differ for disabled built-in mods
+  let mut checked_dirs: Vec<(PathBuf, PathBuf)> =
+    vec![(CCLOADER_DIR_PATH.to_path_buf(), CCLOADER_DIR_PATH.to_path_buf())];
+  checked_dirs.extend(builtin_mods.iter().map(|name| {
+    (MODS_DIR_PATH.join(name), builtin_mod_path(game_data_dir, name))
+  }));
+
+  let mut problems = Vec::new();
+  for (dir, installed_dir) in &checked_dirs {
+    if !unpacked_release_dir.join(dir).is_dir() {
+      warn!("{} isn't in the release archive, skipping it", dir.display());
+      continue;
+    }
+    for unpacked_file in
+      manifest::list_files_recursively(&unpacked_release_dir, dir)?
+    {
+      let unpacked_path = unpacked_release_dir.join(&unpacked_file);
+      let file = Path::new(&unpacked_file)
+        .strip_prefix(dir)
+        .ok()
+        .and_then(|path| {
+          manifest::path_to_manifest_string(&installed_dir.join(path))
+        })
+        .unwrap_or_else(|| unpacked_file.clone());
+      let installed_path = game_data_dir.join(&file);
+      let problem = if !installed_path.is_file() {
+        format!("file '{}' is missing", file)
+      } else if sha256_file(&installed_path)
+        .with_context(|_| format!("couldn't read '{}'", file))?
+        != sha256_file(&unpacked_path)
+          .with_context(|_| format!("couldn't read '{}'", file))?
+      {
+        format!("file '{}' has been changed", file)
+      } else {
+        continue;
+      };
+
+      info!("{}, restoring it", problem);
+      let rel_path = Path::new(&file);
+      if let Some(parent) = rel_path.parent() {
+        transaction.create_dir_all(parent)?;
+      }
+      transaction.install(&unpacked_path, rel_path)?;
+      problems.push(problem);
+    }
+  }
+
+  let ccloader_main = ccloader_package_json_main();
+  let previous_main = match read_package_json_main(game_data_dir)? {
+    Some(main) if main == ccloader_main => Some(main),
+    main => {
+      let problem = format!(
+        "main in package.json is {:?} instead of {:?}",
+        main, ccloader_main
+      );
+      info!("{}, patching it again", problem);
+      problems.push(problem);
+      patch_crosscode_assets(game_data_dir, &mut transaction)
+        .context("Couldn't patch CrossCode assets")?
+    }
+  };
+
+  // the manifest is rewritten even if nothing was broken, so that manifests
+  // without checksums get them
+  transaction
+    .snapshot_file(Path::new(manifest::MANIFEST_FILE_NAME))
+    .and_then(|()| {
+      write_install_manifest(
+        game_data_dir,
+        release_info,
+        previous_manifest,
+        previous_main,
+        builtin_mods,
+      )
+    })
+    .context("Couldn't record the installation manifest")?;
+
+  transaction.commit();
+
+  Ok(problems)
+}
+
+// Steam replaces package.json when the game gets updated, after that the game
+// starts without CCLoader even though its files are still in place.
+pub fn is_package_json_patched(game_data_dir: &Path) -> AppResult<bool> {
+  Ok(
+    read_package_json_main(game_data_dir)?.as_deref()
+      == Some(ccloader_package_json_main().as_str()),
+  )
+}
+
+// Makes package.json start CCLoader again without touching any other files,
+// returns `false` if it already does. The value of `main` which was there
+// comes from the updated game, so it is recorded as the original one.
+pub fn repatch_crosscode_assets(game_data_dir: &Path) -> AppResult<bool> {
+  if is_package_json_patched(game_data_dir)? {
+    return Ok(false);
+  }
+
+  let mut transaction = Transaction::begin(game_data_dir)
+    .context("Couldn't prepare the patching")?;
+
+  let previous_main = patch_crosscode_assets(game_data_dir, &mut transaction)
+    .context("Couldn't patch CrossCode assets")?;
+
+  if let (Some(mut manifest), Some(previous_main)) =
+    (read_install_manifest(game_data_dir), previous_main)
+  {
+    if manifest.original_main != previous_main {
+      info!(
+        "the original main in package.json has changed from {:?} to {:?}",
+        manifest.original_main, previous_main
+      );
+      manifest.original_main = previous_main;
+      transaction
+        .snapshot_file(Path::new(manifest::MANIFEST_FILE_NAME))
+        .and_then(|()| manifest.write(game_data_dir))
+        .context("Couldn't record the installation manifest")?;
+    }
+  }
+
+  transaction.commit();
+
+  Ok(true)
+}
+
+fn read_package_json_main(game_data_dir: &Path) -> AppResult<Option<String>> {
+  let bytes = fs::read(game_data_dir.join(PACKAGE_JSON_FILE_NAME))
+    .context("couldn't read package.json")?;
+  let data: JsonValue =
+    serde_json::from_slice(&bytes).context("couldn't parse package.json")?;
+  Ok(data["main"].as_str().map(str::to_owned))
+}
+
+pub fn detect_installed_ccloader_version(
+  game_data_dir: &Path,
+) -> Option<String> {
+  if let Some(manifest) = read_install_manifest(game_data_dir) {
+    return Some(manifest.ccloader_version);
+  }
+
+  let ccloader_dir = game_data_dir.join(*CCLOADER_DIR_PATH);
+
+  // newer CCLoader releases ship a metadata file next to the loader itself
+  if let Ok(bytes) = fs::read(ccloader_dir.join("metadata.json")) {
+    if let Ok(metadata) = serde_json::from_slice::<JsonValue>(&bytes) {
+      if let Some(version) = metadata["version"].as_str() {
+        return Some(version.to_owned());
+      }
+    }
+  }
+
+  // older ones have the version only hardcoded in the main script, e.g.
+  // `const CCLOADER_VERSION = '2.20.0';`
+  const VERSION_CONST_NAME: &str = "CCLOADER_VERSION";
+  let script = fs::read_to_string(ccloader_dir.join("js/ccloader.js")).ok()?;
+  let rest =
+    &script[script.find(VERSION_CONST_NAME)? + VERSION_CONST_NAME.len()..];
+  let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+  let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+  let rest = &rest[quote.len_utf8()..];
+  let version = &rest[..rest.find(quote)?];
+  if version.is_empty() {
+    return None;
+  }
+  Some(version.to_owned())
+}
+
+// Detects the versions of the game and of nw.js of the installation and checks
+// whether the release supports them.
+pub fn check_game_compatibility(
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+  progress: Rc<dyn Progress>,
+) -> Compatibility {
+  let game_version = game::detect_game_version(game_data_dir);
+  let nwjs_version = game::detect_nwjs_version(game_data_dir, progress);
+  info!(
+    "game version = {:?}, nw.js version = {:?}",
+    game_version, nwjs_version
+  );
+  let compatibility = game::check_compatibility(
+    game_version.as_deref(),
+    nwjs_version.as_deref(),
+    release_info.game_version_req.as_deref(),
+    release_info.nwjs_version_req.as_deref(),
+  );
+  info!("compatibility = {:?}", compatibility);
+  compatibility
+}
+
+pub fn is_newer_version(installed_version: &str, latest_version: &str) -> bool {
+  match (Version::parse(installed_version), Version::parse(latest_version)) {
+    (Ok(installed), Ok(latest)) => latest > installed,
+    _ => installed_version != latest_version,
+  }
+}
+
+#[derive(Debug)]
+pub struct ReleaseInfo {
+  pub version: String,
+  pub source: ReleaseSource,
+  pub root_dir_path: PathBuf,
+  // lowercase hex
+  pub sha256: Option<String>,
+  // semver requirements for the versions of the game and of nw.js
+  pub game_version_req: Option<String>,
+  pub nwjs_version_req: Option<String>,
+}
+
+impl ReleaseInfo {
+  // Makes the installer use an archive which is already on disk instead of
+  // downloading it, the archive must contain the same release.
+  pub fn use_local_archive(&mut self, archive_path: &Path) -> AppResult<()> {
+    check_archive_root_dir(archive_path, self)?;
+    // the checksum can be trusted only if the local file is exactly the
+    // artifact which is listed in the database
+    let file_name = archive_path.file_name().and_then(OsStr::to_str);
+    if let ReleaseSource::Remote(url) = &self.source {
+      if url.path().rsplit('/').next() != file_name {
+        self.sha256 = None;
+      }
+    }
+    self.source = ReleaseSource::Local(archive_path.to_owned());
+    Ok(())
+  }
+
+  // Switches to another release of CCLoader, which has to be published on
+  // GitHub (CCModDB lists only the latest one).
+  pub fn pin_version(
+    &mut self,
+    client: &mut HttpClient,
+    version: &str,
+  ) -> AppResult<()> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    if self.version == version {
+      return Ok(());
+    }
+    let releases = fetch_ccloader_releases(client)
+      .context("Couldn't fetch the list of CCLoader releases")?;
+    self.pin_release(&releases, version)
+  }
+
+  // Release archives are named after their tags (e.g. `.../v2.22.1.zip`
+  // unpacks to `CCLoader-2.22.1/`), so only the file name in the URL and the
+  // version at the end of the directory name are replaced.
+  fn pin_release(
+    &mut self,
+    releases: &[CCLoaderRelease],
+    version: &str,
+  ) -> AppResult<()> {
+    let release = match releases.iter().find(|r| r.version == version) {
+      Some(release) => release,
+      None => bail!(
+        "There is no CCLoader v{} among the releases published on GitHub",
+        version
+      ),
+    };
+
+    let url = match &self.source {
+      ReleaseSource::Remote(url) => url.to_string(),
+      ReleaseSource::Local(_) => {
+        bail!("The version of a local release archive can't be changed")
+      }
+    };
+    let unknown_location_error = || {
+      format!(
+        "Couldn't determine where to download CCLoader v{} from, CCModDB lists an archive without a version in its name",
+        version
+      )
+    };
+
+    let (dir_url, file_name) =
+      url.split_at(url.rfind('/').map_or(0, |i| i + 1));
+    let current_tag = releases
+      .iter()
+      .find(|r| r.version == self.version)
+      .map(|r| r.tag.as_str());
+    let pinned_file_name = match current_tag {
+      Some(tag) if file_name.contains(tag) => {
+        file_name.replacen(tag, &release.tag, 1)
+      }
+      _ if file_name.contains(&self.version) => {
+        file_name.replacen(&self.version, &release.version, 1)
+      }
+      _ => bail!(unknown_location_error()),
+    };
+
+    let root_dir_name = self
+      .root_dir_path
+      .to_str()
+      .and_then(|name| name.strip_suffix(&self.version))
+      .ok_or_else(|| err_msg(unknown_location_error()))?;
+
+    let pinned_url = format!("{}{}", dir_url, pinned_file_name);
+    self.source = ReleaseSource::Remote(
+      Uri::try_from(pinned_url.as_str())
+        .with_context(|_| format!("invalid URL '{}'", pinned_url))?,
+    );
+    self.root_dir_path =
+      PathBuf::from(format!("{}{}", root_dir_name, release.version));
+    self.version = release.version.clone();
+    // the checksum and the requirements in the database are the ones of the
+    // latest release
+    self.sha256 = None;
+    self.game_version_req = None;
+    self.nwjs_version_req = None;
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub enum ReleaseSource {
+  Remote(Uri),
+  Local(PathBuf),
+}
+
+impl fmt::Display for ReleaseSource {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ReleaseSource::Remote(url) => url.fmt(f),
+      ReleaseSource::Local(path) => path.display().fmt(f),
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InstallMode {
+  FreshInstall,
+  Update,
+}
+
+pub fn fetch_latest_release_info(
+  client: &mut HttpClient,
+  database_sources: &[Source],
+) -> AppResult<ReleaseInfo> {
+  let database = Database::load(client, database_sources)?;
+  try_ccmoddb_data_into_release_info(&database).ok_or_else(|| {
+    err_msg("the database doesn't list a valid CCLoader release")
+  })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CCLoaderRelease {
+  pub version: String,
+  // the git tag, usually the version prefixed with `v`
+  pub tag: String,
+}
+
+// Lists the published CCLoader releases, newest first. Pre-releases are
+// included, drafts aren't.
+pub fn fetch_ccloader_releases(
+  client: &mut HttpClient,
+) -> AppResult<Vec<CCLoaderRelease>> {
+  let body = cache::fetch(client, CCLOADER_RELEASES_URL)?;
+  let data: JsonValue = serde_json::from_slice(&body)
+    .context("invalid response received from GitHub")?;
+  let releases = data
+    .as_array()
+    .ok_or_else(|| err_msg("invalid JSON data received from GitHub"))?;
+  Ok(
+    releases
+      .iter()
+      .filter(|release| !release["draft"].as_bool().unwrap_or(false))
+      .filter_map(|release| release["tag_name"].as_str())
+      .map(|tag| CCLoaderRelease {
+        version: tag.strip_prefix('v').unwrap_or(tag).to_owned(),
+        tag: tag.to_owned(),
+      })
+      .collect(),
+  )
+}
+
+// Used when there is no database at hand, relies on release archives being
+// named after the directory inside of them, e.g. `CCLoader-2.22.1.tar.gz`
+// contains `CCLoader-2.22.1/`.
+pub fn release_info_from_archive_file_name(
+  archive_path: &Path,
+) -> AppResult<ReleaseInfo> {
+  let file_name: &str =
+    archive_path.file_name().and_then(OsStr::to_str).unwrap_or("");
+  let parsed: Option<(&str, &str)> = ArchiveFormat::from_file_name(file_name)
+    .and_then(|format| file_name.strip_suffix(format.file_ext()))
+    .and_then(|root_dir_name| {
+      let version = root_dir_name.strip_prefix("CCLoader-")?;
+      if version.is_empty() {
+        return None;
+      }
+      Some((root_dir_name, version))
+    });
+  let (root_dir_name, version) = match parsed {
+    Some(parsed) => parsed,
+    None => bail!(
+      "Couldn't determine the CCLoader version from the archive name '{}', expected a name like 'CCLoader-<version>.tar.gz'",
+      file_name
+    ),
+  };
+
+  let release_info = ReleaseInfo {
+    version: version.to_owned(),
+    source: ReleaseSource::Local(archive_path.to_owned()),
+    root_dir_path: PathBuf::from(root_dir_name),
+    sha256: None,
+    game_version_req: None,
+    nwjs_version_req: None,
+  };
+  check_archive_root_dir(archive_path, &release_info)?;
+  Ok(release_info)
+}
+
+// A local archive of a different release would either fail to unpack or get
+// recorded in the manifest under the wrong version, so the directory inside
+// of it is checked up front.
+fn check_archive_root_dir(
+  archive_path: &Path,
+  release_info: &ReleaseInfo,
+) -> AppResult<()> {
+  let root_dir_name =
+    archive::root_dir_name(archive_path).with_context(|_| {
+      format!("Couldn't read the archive '{}'", archive_path.display())
+    })?;
+  if root_dir_name.as_deref().map(Path::new)
+    == Some(release_info.root_dir_path.as_path())
+  {
+    return Ok(());
+  }
+
+  match root_dir_name
+    .as_deref()
+    .and_then(|name| name.strip_prefix("CCLoader-"))
+  {
+    Some(archive_version) => bail!(
+      "The archive '{}' contains CCLoader v{} instead of v{}",
+      archive_path.display(),
+      archive_version,
+      release_info.version
+    ),
+    None => bail!(
+      "The archive '{}' doesn't contain CCLoader v{}, expected everything in it to be inside of the directory '{}'",
+      archive_path.display(),
+      release_info.version,
+      release_info.root_dir_path.display()
+    ),
+  }
+}
+
+pub fn try_ccmoddb_data_into_release_info(
+  database: &Database,
+) -> Option<ReleaseInfo> {
+  let package = database.package(ccmoddb::CCLOADER_PACKAGE_NAME)?;
+  let main_artifact = package.artifacts.iter().find(|artifact| {
+    artifact.kind == "modZip"
+      && artifact.source.as_deref().is_some_and(|s| s.starts_with("CCLoader-"))
+  })?;
+
+  Some(ReleaseInfo {
+    version: package.version.clone(),
+    source: ReleaseSource::Remote(main_artifact.url.clone()),
+    root_dir_path: PathBuf::from(main_artifact.source.as_ref()?),
+    sha256: main_artifact.sha256.clone(),
+    game_version_req: package.dependency("crosscode").map(|s| s.to_owned()),
+    nwjs_version_req: package.dependency("nwjs").map(|s| s.to_owned()),
+  })
+}
+
+// Downloaded archives are kept in the temporary directory of the OS. Releases
+// of different mods often have the same file names (e.g. `v1.0.0.zip`), so
+// the names are prefixed with a hash of the URL and of the expected checksum,
+// otherwise a partial download of one archive could be resumed with another.
+fn download_path(
+  download_url: &Uri,
+  expected_sha256: Option<&str>,
+) -> io::Result<PathBuf> {
+  let dir = env::temp_dir().join(crate::PKG_NAME);
+  fs::create_dir_all(&dir)?;
+  let file_name = download_url
+    .path()
+    .rsplit('/')
+    .find(|s| !s.is_empty())
+    .unwrap_or("download");
+  let key = format!("{} {}", download_url, expected_sha256.unwrap_or(""));
+  let hash = sha256_hex(key.as_bytes());
+  Ok(dir.join(format!("{}-{}", &hash[..16], file_name)))
+}
+
+#[derive(Debug)]
+pub struct DownloadedArchive {
+  pub path: PathBuf,
+  // archives in the cache are kept for the next installations
+  is_cached: bool,
+}
+
+// Takes an archive from the cache or downloads it into the download directory
+// and verifies it, successfully verified downloads are moved into the cache.
+pub fn download_archive(
+  client: &mut HttpClient,
+  download_url: &Uri,
+  expected_sha256: Option<&str>,
+  progress: Rc<dyn Progress>,
+) -> AppResult<DownloadedArchive> {
+  if let Some(path) = cache::find_archive(download_url, expected_sha256) {
+    info!("using the cached archive {}", path.display());
+    return Ok(DownloadedArchive { path, is_cached: true });
+  }
+
+  let archive_path = download_path(download_url, expected_sha256)
+    .context("couldn't prepare the download directory")?;
+
+  if let Err(e) =
+    download_file(client, download_url.clone(), &archive_path, progress)
+  {
+    // whatever has been downloaded so far is kept, so that the next attempt
+    // can resume from there
+    info!("keeping {} for resuming the download", archive_path.display());
+    return Err(e);
+  }
+
+  if let Err(e) = verify_archive(&archive_path, expected_sha256) {
+    // a corrupted download mustn't be resumed next time
+    remove_file_if_exists(&archive_path);
+    return Err(e);
+  }
+
+  match cache::store_archive(download_url, &archive_path) {
+    Ok(Some(path)) => Ok(DownloadedArchive { path, is_cached: true }),
+    Ok(None) => Ok(DownloadedArchive { path: archive_path, is_cached: false }),
+    Err(e) => {
+      warn!("couldn't put {} into the cache: {}", archive_path.display(), e);
+      Ok(DownloadedArchive { path: archive_path, is_cached: false })
+    }
+  }
+}
+
+pub fn remove_downloaded_archive(archive: &DownloadedArchive) {
+  if !archive.is_cached {
+    remove_file_if_exists(&archive.path);
+  }
+}
+
+fn remove_file_if_exists(path: &Path) {
+  match fs::remove_file(path) {
+    Err(e) if e.kind() != io::ErrorKind::NotFound => {
+      warn!("couldn't delete '{}': {}", path.display(), e)
+    }
+    _ => {}
+  }
+}
+
+fn download_file(
+  client: &mut HttpClient,
+  download_url: Uri,
+  archive_path: &Path,
+  progress: Rc<dyn Progress>,
+) -> AppResult<()> {
+  info!("downloading {} to {}", download_url, archive_path.display());
+  client.set_progress_callback(Some(Box::new(move |downloaded, total| {
+    progress.set_fraction(total.map(|total| downloaded as f64 / total as f64));
+    !progress.is_cancelled()
+  })));
+  let result = client.send_to_file(
+    HttpRequest::get(download_url).body(Vec::new()).unwrap(),
+    archive_path,
+  );
+  client.set_progress_callback(None);
+  let response = result.context("network error")?;
+
+  let status = response.status();
+  if !status.is_success() {
+    bail!("HTTP error: {}", status);
+  }
+
+  Ok(())
+}
+
+// `expected_sha256` is the checksum listed in CCModDB, in lowercase hex.
+pub fn verify_archive(
+  archive_path: &Path,
+  expected_sha256: Option<&str>,
+) -> AppResult<()> {
+  let expected = match expected_sha256 {
+    Some(hash) => hash,
+    None => {
+      warn!(
+        "no checksum is available for {}, skipping the verification",
+        archive_path.display()
+      );
+      return Ok(());
+    }
+  };
+
+  info!("verifying the checksum of {}", archive_path.display());
+  let actual = sha256_file(archive_path).with_context(|_| {
+    format!("Couldn't read the archive '{}'", archive_path.display())
+  })?;
+  if actual != expected {
+    bail!(
+      "The archive '{}' is corrupted or has been tampered with: its SHA-256 checksum is {}, but CCModDB lists {}",
+      archive_path.display(),
+      actual,
+      expected
+    );
+  }
+  Ok(())
+}
+
+// returns the hash as a lowercase hex string
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+  let mut file = File::open(path)?;
+  let mut hasher = Sha256::new();
+  let mut buf = [0; 64 * 1024];
+  loop {
+    let len = file.read(&mut buf)?;
+    if len == 0 {
+      break;
+    }
+    hasher.update(&buf[..len]);
+  }
+  Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+  Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unpack_release_archive(
+  archive_path: &Path,
+  archive_root_dir_path: &Path,
+  game_data_dir: &Path,
+  mode: InstallMode,
+  transaction: &mut Transaction,
+  progress: Rc<dyn Progress>,
+) -> AppResult<Vec<String>> {
+  let unpack_dir = transaction.staging_dir().join("unpacked");
+  info!("unpacking the release archive to {}", unpack_dir.display());
+
+  archive::unpack_archive(
+    archive_path,
+    &unpack_dir,
+    progress.clone(),
+    &|entry_path| match entry_path.strip_prefix(archive_root_dir_path) {
+      Ok(rel_path) => {
+        rel_path.starts_with(*CCLOADER_DIR_PATH)
+          || rel_path.starts_with(*MODS_DIR_PATH)
+      }
+      Err(_) => false,
+    },
+  )?;
+
+  // this is the last chance to cancel, moving the files into place is quick
+  // and doesn't read the archive anymore
+  if progress.is_cancelled() {
+    bail!("cancelled by the user");
+  }
+
+  let unpacked_release_dir = unpack_dir.join(archive_root_dir_path);
+
+  transaction.install(
+    &unpacked_release_dir.join(*CCLOADER_DIR_PATH),
+    *CCLOADER_DIR_PATH,
+  )?;
+
+  transaction.create_dir_all(*MODS_DIR_PATH)?;
+  let mut installed_builtin_mods = Vec::new();
+  for entry in fs::read_dir(unpacked_release_dir.join(*MODS_DIR_PATH))
+    .context("couldn't get the contents of the built-in mods directory")?
+  {
+    let entry = entry
+      .context("couldn't get the contents of the built-in mods directory")?;
+    if let Ok(file_type) = entry.file_type() {
+      if file_type.is_dir() {
+        let name = entry.file_name();
+        // a built-in mod which has been disabled is updated where it is, so
+        // that updating doesn't enable it again
+        let rel_path = match name.to_str() {
+          Some(name) => builtin_mod_path(game_data_dir, name),
+          None => MODS_DIR_PATH.join(&name),
+        };
+        // built-in mods are refreshed on updates, but a fresh installation
+        // must not overwrite mods which the user has put there by hand
+        if mode == InstallMode::Update
+          || !game_data_dir.join(&rel_path).is_dir()
+        {
+          transaction.install(&entry.path(), &rel_path)?;
+          if let Some(name) = name.to_str() {
+            installed_builtin_mods.push(name.to_owned());
+          }
+        } else {
+          warn!("{} has already been installed, skipping", rel_path.display());
+        }
+      }
+    }
+  }
+
+  Ok(installed_builtin_mods)
+}
+
+fn ccloader_package_json_main() -> String {
+  format!(
+    "{}/index.html",
+    // unwrap is used because a) this is a compile time constant, so I can
+    // guarantee that it's properly encoded because b) JSON strings can contain
+    // only valid Unicode characters
+    CCLOADER_DIR_PATH.to_str().unwrap()
+  )
+}
+
+// returns the value of `main` before patching
+fn patch_crosscode_assets(
+  game_data_dir: &Path,
+  transaction: &mut Transaction,
+) -> AppResult<Option<String>> {
+  transaction.snapshot_file(Path::new(PACKAGE_JSON_FILE_NAME))?;
+  transaction
+    .snapshot_file(Path::new(manifest::PACKAGE_JSON_BACKUP_FILE_NAME))?;
+  set_package_json_main(game_data_dir, &ccloader_package_json_main(), true)
+}
+
+fn unpatch_crosscode_assets(
+  game_data_dir: &Path,
+  original_main: &str,
+  transaction: &mut Transaction,
+) -> AppResult<()> {
+  transaction.snapshot_file(Path::new(PACKAGE_JSON_FILE_NAME))?;
+  set_package_json_main(game_data_dir, original_main, false)?;
+  Ok(())
+}
+
+fn set_package_json_main(
+  game_data_dir: &Path,
+  main: &str,
+  back_up_original: bool,
+) -> AppResult<Option<String>> {
+  use std::fs::{File, OpenOptions};
+  use std::io::{Read, Seek, SeekFrom};
+
+  let package_json_path = game_data_dir.join(PACKAGE_JSON_FILE_NAME);
+  info!("setting main in {} to {}", package_json_path.display(), main);
+
+  let mut package_json_file: File = OpenOptions::new()
+    .create(false)
+    .read(true)
+    .write(true)
+    .open(package_json_path)
+    .context("couldn't open package.json")?;
+
+  let mut package_json_bytes = Vec::new();
+  package_json_file
+    .read_to_end(&mut package_json_bytes)
+    .context("couldn't read package.json")?;
+
+  let mut package_json_data: JsonValue =
+    serde_json::from_slice(&package_json_bytes)
+      .context("couldn't parse package.json")?;
+  if !package_json_data.is_object() {
+    bail!("data in package.json is invalid");
+  }
+
+  let previous_main: Option<String> =
+    package_json_data["main"].as_str().map(str::to_owned);
+
+  // the backup is refreshed every time the game is patched from the vanilla
+  // state, this way it always contains the latest version of package.json
+  // shipped with the game
+  if back_up_original && previous_main.as_deref() != Some(main) {
+    let backup_path =
+      game_data_dir.join(manifest::PACKAGE_JSON_BACKUP_FILE_NAME);
+    info!("backing up the original package.json to {}", backup_path.display());
+    fs::write(&backup_path, &package_json_bytes)
+      .context("couldn't back up package.json")?;
+  }
+
+  package_json_data["main"] = JsonValue::String(main.to_owned());
+
+  // truncate the file, then overwrite it with the patched data
+
+  // set_len can return an error only if the file isn't opened for writing, or
+  // the desired length would cause an integer overflow
+  package_json_file.set_len(0).unwrap();
+  // seek can fail only when called with a negative offset
+  package_json_file.seek(SeekFrom::Start(0)).unwrap();
+
+  serde_json::to_writer_pretty(&mut package_json_file, &package_json_data)
+    .context("couldn't write patched package.json")?;
+
+  Ok(previous_main)
+}
+
+fn write_install_manifest(
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+  previous_manifest: Option<InstallManifest>,
+  previous_main: Option<String>,
+  installed_builtin_mods: Vec<String>,
+) -> AppResult<()> {
+  let ccloader_main = ccloader_package_json_main();
+  let (original_main, mut builtin_mods) = match previous_manifest {
+    // when reinstalling over an already patched package.json the real
+    // original value can only be taken from the previous manifest
+    Some(m) if previous_main.as_deref() == Some(&ccloader_main) => {
+      (m.original_main, m.builtin_mods)
+    }
+    Some(m) => (
+      previous_main.unwrap_or_else(|| ORIGINAL_PACKAGE_JSON_MAIN.to_owned()),
+      m.builtin_mods,
+    ),
+    None => (
+      previous_main
+        .filter(|main| *main != ccloader_main)
+        .unwrap_or_else(|| ORIGINAL_PACKAGE_JSON_MAIN.to_owned()),
+      Vec::new(),
+    ),
+  };
+  for name in installed_builtin_mods {
+    if !builtin_mods.contains(&name) {
+      builtin_mods.push(name);
+    }
+  }
+  builtin_mods.sort();
+
+  let mut files =
+    manifest::list_files_recursively(game_data_dir, *CCLOADER_DIR_PATH)?;
+  for name in &builtin_mods {
+    let rel_path = builtin_mod_path(game_data_dir, name);
+    if game_data_dir.join(&rel_path).is_dir() {
+      files.extend(manifest::list_files_recursively(game_data_dir, &rel_path)?);
+    }
+  }
+
+  let mut checksums = BTreeMap::new();
+  for file in &files {
+    let checksum = sha256_file(&game_data_dir.join(file))
+      .with_context(|_| format!("couldn't read '{}'", file))?;
+    checksums.insert(file.clone(), checksum);
+  }
+
+  InstallManifest {
+    installer_version: crate::PKG_VERSION.to_owned(),
+    ccloader_version: release_info.version.clone(),
+    source_url: release_info.source.to_string(),
+    original_main,
+    builtin_mods,
+    files,
+    checksums,
+  }
+  .write(game_data_dir)
+}
+
+pub fn read_install_manifest(game_data_dir: &Path) -> Option<InstallManifest> {
+  match InstallManifest::read(game_data_dir) {
+    Ok(manifest) => manifest,
+    Err(e) => {
+      warn!("{} (ignoring it)", e);
+      None
+    }
+  }
+}
+
+pub fn uninstall_ccloader(
+  game_data_dir: &Path,
+  remove_builtin_mods: bool,
+) -> AppResult<()> {
+  let manifest = read_install_manifest(game_data_dir);
+  let original_main: &str =
+    manifest.as_ref().map_or(ORIGINAL_PACKAGE_JSON_MAIN, |m| &m.original_main);
+
+  let mut transaction = Transaction::begin(game_data_dir)?;
+
+  unpatch_crosscode_assets(game_data_dir, original_main, &mut transaction)
+    .context("couldn't restore package.json")?;
+
+  let mut remove = |rel_path: &Path| -> AppResult<()> {
+    info!("deleting {}", rel_path.display());
+    transaction.remove(rel_path)
+  };
+
+  remove(*CCLOADER_DIR_PATH)?;
+
+  if remove_builtin_mods {
+    for name in installed_builtin_mod_names(game_data_dir, manifest.as_ref()) {
+      remove(&builtin_mod_path(game_data_dir, &name))?;
+    }
+  }
+
+  for file_name in
+    &[manifest::MANIFEST_FILE_NAME, manifest::PACKAGE_JSON_BACKUP_FILE_NAME]
+  {
+    if game_data_dir.join(file_name).is_file() {
+      remove(Path::new(file_name))?;
+    }
+  }
+
+  transaction.commit();
+
+  Ok(())
+}
+
+pub fn installed_builtin_mod_names(
+  game_data_dir: &Path,
+  manifest: Option<&InstallManifest>,
+) -> Vec<String> {
+  let names: Vec<String> = match manifest {
+    Some(manifest) => manifest.builtin_mods.clone(),
+    None => BUILTIN_MOD_NAMES.iter().map(|&s| s.to_owned()).collect(),
+  };
+  names
+    .into_iter()
+    .filter(|name| {
+      game_data_dir.join(builtin_mod_path(game_data_dir, name)).is_dir()
+    })
+    .collect()
+}
+
+// built-in mods which the user has disabled live in the disabled mods
+// directory, the returned path is relative to the game data directory
+fn builtin_mod_path(game_data_dir: &Path, name: &str) -> PathBuf {
+  let enabled_path = MODS_DIR_PATH.join(name);
+  let disabled_path = DISABLED_MODS_DIR_PATH.join(name);
+  if !game_data_dir.join(&enabled_path).is_dir()
+    && game_data_dir.join(&disabled_path).is_dir()
+  {
+    disabled_path
+  } else {
+    enabled_path
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a fresh directory for every test, the tests run in parallel
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!(
+      "ccloader-installer-test-{}-installer-{}",
+      std::process::id(),
+      name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  const ABC_SHA256: &str =
+    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+  #[test]
+  fn sha256_file_hashes_the_whole_file() {
+    let dir = test_dir("sha256");
+    let path = dir.join("abc.txt");
+    fs::write(&path, "abc").unwrap();
+    assert_eq!(sha256_file(&path).unwrap(), ABC_SHA256);
+    assert_eq!(sha256_hex(b"abc"), ABC_SHA256);
+
+    // longer than the read buffer
+    let data = vec![b'x'; 200 * 1024 + 7];
+    fs::write(&path, &data).unwrap();
+    assert_eq!(sha256_file(&path).unwrap(), sha256_hex(&data));
+
+    assert!(sha256_file(&dir.join("missing.txt")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn verify_archive_checks_the_checksum() {
+    let dir = test_dir("verify");
+    let path = dir.join("CCLoader-2.22.1.zip");
+    fs::write(&path, "abc").unwrap();
+
+    verify_archive(&path, Some(ABC_SHA256)).unwrap();
+    // without a checksum there is nothing to compare with
+    verify_archive(&path, None).unwrap();
+
+    fs::write(&path, "abd").unwrap();
+    let error = verify_archive(&path, Some(ABC_SHA256)).unwrap_err();
+    assert!(error.contains("is corrupted or has been tampered with"));
+    assert!(error.contains(ABC_SHA256));
+    assert!(error.contains(&sha256_hex(b"abd")));
+
+    assert!(verify_archive(&dir.join("missing.zip"), Some(ABC_SHA256)).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  fn release_info(url: &str) -> ReleaseInfo {
+    ReleaseInfo {
+      version: "2.22.1".to_owned(),
+      source: ReleaseSource::Remote(Uri::try_from(url).unwrap()),
+      root_dir_path: PathBuf::from("CCLoader-2.22.1"),
+      sha256: Some("00".repeat(32)),
+      game_version_req: Some("^1.1.0".to_owned()),
+      nwjs_version_req: None,
+    }
+  }
+
+  fn releases(tags: &[&str]) -> Vec<CCLoaderRelease> {
+    tags
+      .iter()
+      .map(|&tag| CCLoaderRelease {
+        version: tag.strip_prefix('v').unwrap_or(tag).to_owned(),
+        tag: tag.to_owned(),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn pin_release_replaces_only_the_file_name() {
+    let mut info =
+      release_info("https://example.com/2.22.1/archive/v2.22.1.zip");
+    info.pin_release(&releases(&["v2.22.1", "v2.20.0"]), "2.20.0").unwrap();
+    assert_eq!(info.version, "2.20.0");
+    assert_eq!(
+      info.source.to_string(),
+      "https://example.com/2.22.1/archive/v2.20.0.zip"
+    );
+    assert_eq!(info.root_dir_path, PathBuf::from("CCLoader-2.20.0"));
+    assert_eq!(info.sha256, None);
+    assert_eq!(info.game_version_req, None);
+  }
+
+  #[test]
+  fn pin_release_uses_the_published_tag() {
+    let mut info = release_info("https://example.com/archive/v2.22.1.zip");
+    info
+      .pin_release(&releases(&["v2.22.1", "2.20.0-beta"]), "2.20.0-beta")
+      .unwrap();
+    assert_eq!(
+      info.source.to_string(),
+      "https://example.com/archive/2.20.0-beta.zip"
+    );
+  }
+
+  #[test]
+  fn pin_release_rejects_unpublished_versions() {
+    let mut info = release_info("https://example.com/archive/v2.22.1.zip");
+    let error = info
+      .pin_release(&releases(&["v2.22.1", "v2.20.0"]), "2.2.0")
+      .unwrap_err();
+    assert!(error.to_string().contains("There is no CCLoader v2.2.0"));
+    assert_eq!(info.version, "2.22.1");
+    assert_eq!(
+      info.source.to_string(),
+      "https://example.com/archive/v2.22.1.zip"
+    );
+  }
+
+  #[test]
+  fn pin_release_rejects_file_names_without_a_version() {
+    let mut info = release_info("https://example.com/v2.22.1/latest.zip");
+    assert!(info
+      .pin_release(&releases(&["v2.22.1", "v2.20.0"]), "2.20.0")
+      .is_err());
+  }
+}
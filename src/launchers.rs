@@ -0,0 +1,198 @@
+// Detection of CrossCode copies installed without Steam on Linux, i.e. from GOG
+// through one of the third-party launchers or from itch.io with its app. None
+// of these launchers have a stable API for listing the installed games, so
+// their configuration files are read on a best-effort basis, and all returned
+// paths have to go through `is_game_data_dir` anyway.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde_json::Value as JsonValue;
+
+// the ID of CrossCode on GOG
+const GOG_PRODUCT_ID: &str = "1252295864";
+
+// Returns the candidates together with the name of the launcher they come
+// from.
+pub fn possible_game_data_locations() -> Vec<(PathBuf, &'static str)> {
+  let mut result = Vec::new();
+  let home = match dirs::home_dir() {
+    Some(home) => home,
+    None => return result,
+  };
+  let config_dir = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+  let data_dir = dirs::data_dir().unwrap_or_else(|| home.join(".local/share"));
+
+  for install_dir in heroic_gog_installations(&home, &config_dir) {
+    push_gog_install_dir(&mut result, install_dir, "GOG (Heroic)");
+  }
+  for install_dir in lutris_installations(&home, &config_dir, &data_dir) {
+    push_gog_install_dir(&mut result, install_dir, "GOG (Lutris)");
+  }
+  for install_dir in minigalaxy_installations(&home, &config_dir) {
+    push_gog_install_dir(&mut result, install_dir, "GOG (minigalaxy)");
+  }
+  for path in itch_installations(&config_dir) {
+    result.push((path, "itch.io"));
+  }
+
+  result
+}
+
+// the Linux installers from GOG put the game files into the `game`
+// subdirectory, whereas the Windows ones run through Wine don't
+fn push_gog_install_dir(
+  result: &mut Vec<(PathBuf, &'static str)>,
+  install_dir: PathBuf,
+  source: &'static str,
+) {
+  result.push((install_dir.join("game"), source));
+  result.push((install_dir, source));
+}
+
+fn looks_like_crosscode(path: &Path) -> bool {
+  path.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+    name.to_ascii_lowercase().replace(|c: char| !c.is_alphanumeric(), "")
+      == "crosscode"
+  })
+}
+
+fn read_json_file(path: &Path) -> Option<JsonValue> {
+  let bytes = fs::read(path).ok()?;
+  info!("reading {}", path.display());
+  serde_json::from_slice(&bytes).ok()
+}
+
+// https://github.com/Heroic-Games-Launcher/HeroicGamesLauncher
+fn heroic_gog_installations(home: &Path, config_dir: &Path) -> Vec<PathBuf> {
+  let mut result = Vec::new();
+  let heroic_config_dirs = [
+    config_dir.join("heroic"),
+    home.join(".var/app/com.heroicgameslauncher.hgl/config/heroic"),
+  ];
+
+  for heroic_config_dir in &heroic_config_dirs {
+    let installed =
+      match read_json_file(&heroic_config_dir.join("gog_store/installed.json"))
+      {
+        Some(installed) => installed,
+        None => continue,
+      };
+    let games = match installed["installed"].as_array() {
+      Some(games) => games,
+      None => continue,
+    };
+
+    for game in games {
+      if let Some(install_path) = game["install_path"].as_str() {
+        let install_path = PathBuf::from(install_path);
+        if game["appName"].as_str() == Some(GOG_PRODUCT_ID)
+          || looks_like_crosscode(&install_path)
+        {
+          result.push(install_path);
+        }
+      }
+    }
+  }
+
+  result
+}
+
+// https://lutris.net/, its game configs are YAML files, but only a few keys
+// from them are needed, so they are scanned line by line instead of pulling in
+// a YAML parser
+fn lutris_installations(
+  home: &Path,
+  config_dir: &Path,
+  data_dir: &Path,
+) -> Vec<PathBuf> {
+  // the directory where Lutris installs games by default
+  let mut result = vec![home.join("Games/crosscode")];
+
+  for games_config_dir in
+    &[config_dir.join("lutris/games"), data_dir.join("lutris/games")]
+  {
+    let entries = match fs::read_dir(games_config_dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+      let file_name = entry.file_name();
+      let file_name = file_name.to_string_lossy();
+      // the files are named after the game slug, e.g. `crosscode-1612345678`
+      if !(file_name.starts_with("crosscode") && file_name.ends_with(".yml")) {
+        continue;
+      }
+      let text = match fs::read_to_string(entry.path()) {
+        Ok(text) => text,
+        Err(_) => continue,
+      };
+      info!("reading {}", entry.path().display());
+
+      for line in text.lines() {
+        let line = line.trim();
+        let (key, value) = match line.find(':') {
+          Some(index) => (&line[..index], line[index + 1..].trim()),
+          None => continue,
+        };
+        let value = value.trim_matches(|c| c == '\'' || c == '"');
+        // relative paths are resolved by Lutris against other settings, so
+        // they are not of much use here
+        if !Path::new(value).is_absolute() {
+          continue;
+        }
+        match key {
+          "working_dir" => result.push(PathBuf::from(value)),
+          "exe" => {
+            if let Some(parent) = Path::new(value).parent() {
+              result.push(parent.to_owned());
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+  }
+
+  result
+}
+
+// https://sharkwouter.github.io/minigalaxy/, installs every game into a
+// directory named after the game inside of a configurable directory
+fn minigalaxy_installations(home: &Path, config_dir: &Path) -> Vec<PathBuf> {
+  let default_install_dir = home.join("GOG Games");
+  let install_dir = read_json_file(&config_dir.join("minigalaxy/config.json"))
+    .and_then(|config| config["install_dir"].as_str().map(PathBuf::from))
+    .unwrap_or(default_install_dir);
+  vec![install_dir.join("CrossCode")]
+}
+
+// https://itch.io/app, installs games into its config directory and puts the
+// files directly into the directory of the game
+fn itch_installations(config_dir: &Path) -> Vec<PathBuf> {
+  let mut result = Vec::new();
+  let entries = match fs::read_dir(config_dir.join("itch/apps")) {
+    Ok(entries) => entries,
+    Err(_) => return result,
+  };
+
+  for entry in entries.filter_map(|entry| entry.ok()) {
+    let path = entry.path();
+    if !looks_like_crosscode(&path) {
+      continue;
+    }
+    // some builds are packed with an extra top-level directory
+    if let Ok(sub_entries) = fs::read_dir(&path) {
+      for sub_entry in sub_entries.filter_map(|entry| entry.ok()) {
+        if sub_entry.file_type().is_ok_and(|t| t.is_dir()) {
+          result.push(sub_entry.path());
+        }
+      }
+    }
+    result.push(path);
+  }
+
+  result
+}
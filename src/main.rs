@@ -1,45 +1,58 @@
-use std::convert::TryFrom;
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
 use std::str;
 
-use flate2::bufread::GzDecoder;
-use lazy_static::lazy_static;
 use log::{error, info, warn};
-use serde_json::Value as JsonValue;
-use tar::Archive;
 
 #[macro_use]
 mod error;
-use error::err_msg;
 
+mod archive;
 mod ascii_to_int;
+mod cache;
+mod ccmoddb;
+mod cli;
+mod config;
 mod fancy_logger;
+mod game;
 mod http_client;
+mod installer;
+#[cfg(target_os = "linux")]
+mod launchers;
+mod manifest;
+mod mods;
 mod native_ui;
+mod progress;
+mod resolver;
+mod steam;
+mod transaction;
 
 use error::{Result as AppResult, ResultExt};
-use http_client::{Body, HttpClient, Request as HttpRequest, Uri};
+use game::Compatibility;
+use http_client::HttpClient;
+use installer::{
+  InstallMode, ReleaseInfo, CCLOADER_DIR_PATH, MODS_DIR_PATH,
+  PACKAGE_JSON_FILE_NAME,
+};
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const CCMODDB_DATA_URL: &str =
-  "https://raw.githubusercontent.com/CCDirectLink/CCModDB/refs/heads/stable/npDatabase.json";
-
 const BUG_REPORT_TEXT: &str =
   "Please, contact @dmitmel on either GitHub, CrossCode official Discord server, or CCDirectLink Discord server. Bugs can be reported at https://github.com/dmitmel/ccloader-installer/issues";
 
-lazy_static! {
-  static ref CCLOADER_DIR_PATH: &'static Path = Path::new("ccloader");
-  static ref MODS_DIR_PATH: &'static Path = Path::new("assets/mods");
-}
-
 fn main() {
   fancy_logger::init();
   info!("{} v{}", PKG_NAME, PKG_VERSION);
 
   curl::init();
+
+  if let Some(args) = cli::args() {
+    fancy_logger::set_panic_hook();
+    process::exit(cli::run(args));
+  }
+
   native_ui::init();
 
   fancy_logger::set_panic_hook();
@@ -67,39 +80,324 @@ fn try_run() -> AppResult<()> {
   };
   info!("game data dir = {}", game_data_dir.display());
 
-  let ccloader_dir = game_data_dir.join(&*CCLOADER_DIR_PATH);
-  if ccloader_dir.is_dir() {
-    bail!("The game data directory already contains a CCLoader installation (updating CCLoader isn't supported yet)")
+  let ccloader_dir = game_data_dir.join(*CCLOADER_DIR_PATH);
+  let mut installed_version = None;
+  let (mode, release_choice) = if ccloader_dir.is_dir() {
+    installed_version =
+      installer::detect_installed_ccloader_version(&game_data_dir);
+    info!("installed CCLoader version = {:?}", installed_version);
+
+    if matches!(installer::is_package_json_patched(&game_data_dir), Ok(false)) {
+      match ask_for_repatching_confirmation(installed_version.as_deref()) {
+        Some(true) => return run_repatching(&game_data_dir),
+        Some(false) => {}
+        None => return Ok(()),
+      }
+    }
+
+    match ask_what_to_do_with_existing_installation(
+      &game_data_dir,
+      installed_version.as_deref(),
+    ) {
+      Some(ExistingInstallationAction::Update) => {
+        (InstallMode::Update, ReleaseChoice::Stable)
+      }
+      Some(ExistingInstallationAction::ChooseRelease) => match ask_for_release(
+        &mut client,
+        "Which CCLoader release do you want to install?",
+      )? {
+        Some(release_choice) => (InstallMode::Update, release_choice),
+        None => return Ok(()),
+      },
+      Some(ExistingInstallationAction::Repair) => {
+        return run_repair(
+          &mut client,
+          &game_data_dir,
+          installed_version.as_deref(),
+        );
+      }
+      Some(ExistingInstallationAction::ManageMods) => {
+        return run_mod_management(&game_data_dir);
+      }
+      Some(ExistingInstallationAction::Uninstall) => {
+        return run_uninstallation(&game_data_dir);
+      }
+      None => return Ok(()),
+    }
+  } else {
+    match ask_for_installation_confirmation(&mut client, &game_data_dir)? {
+      Some(release_choice) => (InstallMode::FreshInstall, release_choice),
+      None => return Ok(()),
+    }
+  };
+
+  let mut database_sources = default_database_sources()?;
+  if release_choice == ReleaseChoice::Testing
+    && !ccmoddb::switch_channel(
+      &mut database_sources,
+      ccmoddb::Channel::Testing,
+    )
+  {
+    warn!("the official database isn't used, there is no testing channel");
   }
+  let release_info =
+    match installer::fetch_latest_release_info(&mut client, &database_sources)
+      .context("Couldn't fetch the latest release information")
+      .and_then(|mut release_info| {
+        if let ReleaseChoice::Version(version) = &release_choice {
+          release_info.pin_version(&mut client, version)?;
+        }
+        Ok(release_info)
+      }) {
+      Ok(release_info) => release_info,
+      Err(error) => {
+        error!("{}", error);
+        match ask_for_local_archive(&error) {
+          Some(archive_path) => {
+            installer::release_info_from_archive_file_name(&archive_path)?
+          }
+          None => return Ok(()),
+        }
+      }
+    };
 
-  let user_wants_to_continue =
-    ask_for_installation_confirmation(&game_data_dir);
-  if !user_wants_to_continue {
+  info!("release info = {:?}", release_info);
+
+  if !check_game_compatibility(&game_data_dir, &release_info) {
     return Ok(());
   }
 
-  let release_info = fetch_latest_release_info(&mut client)
-    .context("Couldn't fetch the latest release information")?;
+  if mode == InstallMode::Update {
+    let user_wants_to_continue = ask_for_update_confirmation(
+      &game_data_dir,
+      installed_version.as_deref(),
+      &release_info.version,
+      release_choice == ReleaseChoice::Stable,
+    );
+    if !user_wants_to_continue {
+      return Ok(());
+    }
+  }
+
+  let progress_window = Rc::new(native_ui::ProgressWindow::new());
+  let result = installer::install_release(
+    &mut client,
+    &game_data_dir,
+    &release_info,
+    mode,
+    progress_window.clone(),
+  );
+  let was_cancelled = progress_window.is_cancelled();
+  drop(progress_window);
+  if let Err(error) = result {
+    if was_cancelled {
+      // the error in this case only says that the installation was
+      // interrupted and everything has been rolled back, so it isn't shown
+      info!("installation was cancelled by the user: {}", error);
+      return Ok(());
+    }
+    return Err(error);
+  }
 
-  info!("release info = {:?}", release_info);
+  info!("installation completed successfully");
 
-  let compressed_archive_data =
-    download_release_archive(&mut client, release_info.download_url)
-      .context("Couldn't donwload the latest CCLoader release")?;
+  show_installation_success_alert(&game_data_dir, mode);
 
-  unpack_release_archive(
-    compressed_archive_data,
-    &release_info.root_dir_path,
-    &game_data_dir,
+  Ok(())
+}
+
+fn default_database_sources() -> AppResult<Vec<ccmoddb::Source>> {
+  Ok(config::database_sources()?.unwrap_or_else(|| {
+    vec![ccmoddb::Source::Official(ccmoddb::Channel::Stable)]
+  }))
+}
+
+// Restores the files of the installed release, which can get lost or changed
+// e.g. when the game files are verified by Steam.
+fn run_repair(
+  client: &mut HttpClient,
+  game_data_dir: &Path,
+  installed_version: Option<&str>,
+) -> AppResult<()> {
+  use native_ui::*;
+
+  let installed_version = match installed_version {
+    Some(v) => v,
+    None => {
+      show_alert(AlertConfig {
+        icon: AlertIcon::Error,
+        title: "Couldn't determine the installed CCLoader version, so it can't be repaired.".to_owned(),
+        description: Some(
+          "Update (or reinstall) CCLoader instead.".to_owned(),
+        ),
+        buttons: AlertButtons::Ok,
+      });
+      return Ok(());
+    }
+  };
+
+  let release_info = match installer::fetch_latest_release_info(
+    client,
+    &default_database_sources()?,
   )
-  .context("Couldn't unpack the CCLoader release archive")?;
+  .context("Couldn't fetch the release information")
+  .and_then(|mut release_info| {
+    if release_info.version != installed_version {
+      release_info.pin_version(client, installed_version)?;
+    }
+    Ok(release_info)
+  }) {
+    Ok(release_info) => release_info,
+    Err(error) => {
+      error!("{}", error);
+      match ask_for_local_archive(&error) {
+        Some(archive_path) => {
+          let release_info =
+            installer::release_info_from_archive_file_name(&archive_path)?;
+          if release_info.version != installed_version {
+            bail!(
+              "The archive contains CCLoader v{}, but v{} is installed",
+              release_info.version,
+              installed_version
+            );
+          }
+          release_info
+        }
+        None => return Ok(()),
+      }
+    }
+  };
+  info!("release info = {:?}", release_info);
 
-  patch_crosscode_assets(&game_data_dir)
-    .context("Couldn't patch CrossCode assets")?;
+  let progress_window = Rc::new(ProgressWindow::new());
+  let result = installer::repair_installation(
+    client,
+    game_data_dir,
+    &release_info,
+    progress_window.clone(),
+  );
+  let was_cancelled = progress_window.is_cancelled();
+  drop(progress_window);
+  let problems = match result {
+    Ok(problems) => problems,
+    Err(error) if was_cancelled => {
+      info!("repair was cancelled by the user: {}", error);
+      return Ok(());
+    }
+    Err(error) => return Err(error),
+  };
 
-  info!("installation completed successfully");
+  info!("repair completed successfully");
+
+  show_alert(AlertConfig {
+    icon: AlertIcon::Info,
+    title: if problems.is_empty() {
+      format!(
+        "CCLoader v{} is intact, nothing had to be repaired.",
+        installed_version
+      )
+    } else {
+      format!("CCLoader v{} has been successfully repaired!", installed_version)
+    },
+    description: if problems.is_empty() {
+      None
+    } else {
+      Some(format!(
+        "The following problems have been fixed:\n{}",
+        problems.join("\n")
+      ))
+    },
+    buttons: AlertButtons::Ok,
+  });
+
+  Ok(())
+}
+
+fn run_repatching(game_data_dir: &Path) -> AppResult<()> {
+  use native_ui::*;
 
-  show_installation_success_alert(&game_data_dir);
+  installer::repatch_crosscode_assets(game_data_dir)
+    .context("Couldn't patch package.json")?;
+
+  info!("package.json has been patched successfully");
+
+  show_alert(AlertConfig {
+    icon: AlertIcon::Info,
+    title: "CCLoader has been successfully reactivated!".to_owned(),
+    description: Some(
+      "The game will start with mods again. This has to be repeated after every update of the game."
+        .to_owned(),
+    ),
+    buttons: AlertButtons::Ok,
+  });
+
+  Ok(())
+}
+
+fn run_uninstallation(game_data_dir: &Path) -> AppResult<()> {
+  let remove_builtin_mods = match ask_for_builtin_mods_removal(game_data_dir) {
+    Some(b) => b,
+    None => return Ok(()),
+  };
+
+  installer::uninstall_ccloader(game_data_dir, remove_builtin_mods)
+    .context("Couldn't uninstall CCLoader")?;
+
+  info!("uninstallation completed successfully");
+
+  show_uninstallation_success_alert();
+
+  Ok(())
+}
+
+// Lets the user choose which mods are loaded, e.g. to find out which one of
+// them breaks the game.
+fn run_mod_management(game_data_dir: &Path) -> AppResult<()> {
+  use native_ui::*;
+
+  let installed_mods = mods::installed_mods(game_data_dir)
+    .context("Couldn't list the installed mods")?;
+  if installed_mods.is_empty() {
+    show_alert(AlertConfig {
+      icon: AlertIcon::Info,
+      title: "No mods have been installed yet.".to_owned(),
+      description: None,
+      buttons: AlertButtons::Ok,
+    });
+    return Ok(());
+  }
+
+  let items: Vec<String> = installed_mods
+    .iter()
+    .map(|installed_mod| match &installed_mod.version {
+      Some(version) => format!("{} v{}", installed_mod.name, version),
+      None => installed_mod.name.clone(),
+    })
+    .collect();
+  let checked: Vec<bool> = installed_mods.iter().map(|m| m.enabled).collect();
+  let new_checked = match show_checklist_dialog(
+    "Choose the mods which CCLoader should load:",
+    &items,
+    &checked,
+  ) {
+    Some(new_checked) => new_checked,
+    None => return Ok(()),
+  };
+
+  for (installed_mod, &enabled) in installed_mods.iter().zip(&new_checked) {
+    mods::set_mod_enabled(game_data_dir, installed_mod, enabled)?;
+  }
+
+  info!("mods have been successfully enabled or disabled");
+
+  show_alert(AlertConfig {
+    icon: AlertIcon::Info,
+    title: "The changes have been saved!".to_owned(),
+    description: Some(
+      "They will take effect the next time the game is started.".to_owned(),
+    ),
+    buttons: AlertButtons::Ok,
+  });
 
   Ok(())
 }
@@ -123,8 +421,11 @@ fn ask_for_game_data_dir() -> Option<PathBuf> {
 
   if try_to_autodetect {
     info!("trying to autodetect the game data directory");
-    if let Some(p) = autodetect_game_data_dir() {
-      return Some(p);
+    let mut installations = autodetect_game_data_dirs();
+    if installations.len() > 1 {
+      return ask_to_choose_installation(installations);
+    } else if let Some(installation) = installations.pop() {
+      return Some(installation.path);
     } else {
       info!("autodetection failed");
       match show_alert(AlertConfig {
@@ -164,269 +465,384 @@ fn ask_for_game_data_dir() -> Option<PathBuf> {
   None
 }
 
-fn autodetect_game_data_dir() -> Option<PathBuf> {
-  possible_game_data_locations().into_iter().find(|path| is_game_data_dir(path))
+// A game data directory found by the autodetection.
+#[derive(Debug, Clone)]
+pub struct DetectedInstallation {
+  pub path: PathBuf,
+  // where the game has been installed from, e.g. Steam or GOG
+  pub source: &'static str,
+  pub game_version: Option<String>,
+}
+
+impl DetectedInstallation {
+  pub fn describe(&self) -> String {
+    format!(
+      "CrossCode {} from {} at {}",
+      self.game_version.as_deref().unwrap_or("(unknown version)"),
+      self.source,
+      self.path.display(),
+    )
+  }
+}
+
+fn autodetect_game_data_dirs() -> Vec<DetectedInstallation> {
+  let mut result: Vec<DetectedInstallation> = Vec::new();
+  for (path, source) in possible_game_data_locations() {
+    if is_game_data_dir(&path)
+      && !result.iter().any(|i| steam::is_same_dir(&i.path, &path))
+    {
+      let game_version = game::detect_game_version(&path);
+      let installation = DetectedInstallation { path, source, game_version };
+      info!("found {}", installation.describe());
+      result.push(installation);
+    }
+  }
+  result
 }
 
 fn is_game_data_dir(path: &Path) -> bool {
   info!("checking {}", path.display());
   path.is_dir()
-    && path.join("package.json").is_file()
+    && path.join(PACKAGE_JSON_FILE_NAME).is_file()
     && path.join("assets").is_dir()
     && path.join("assets/node-webkit.html").is_file()
 }
 
-#[cfg(target_os = "linux")]
-fn possible_game_data_locations() -> Vec<PathBuf> {
-  let mut result = Vec::with_capacity(1);
-  if let Some(home) = dirs::home_dir() {
-    result.push(home.join(".steam/steam/steamapps/common/CrossCode"));
-  }
-  result
-}
-
-#[cfg(target_os = "macos")]
-fn possible_game_data_locations() -> Vec<PathBuf> {
-  let mut result = Vec::with_capacity(1);
-  if let Some(home) = dirs::home_dir() {
-    result.push(home.join(
-      "Library/Application Support/Steam/steamapps/common/CrossCode/CrossCode.app/Contents/Resources/app.nw",
-    ));
+fn possible_game_data_locations() -> Vec<(PathBuf, &'static str)> {
+  let mut result: Vec<(PathBuf, &'static str)> = Vec::new();
+  for (steam_dir, source) in steam::steam_dirs() {
+    for path in steam::find_crosscode_installations(&steam_dir) {
+      result.push((path, source));
+    }
+    // in case the app manifest has gone missing
+    result.push((steam::default_game_data_dir(&steam_dir), source));
   }
+  #[cfg(target_os = "linux")]
+  result.extend(launchers::possible_game_data_locations());
   result
 }
 
-#[cfg(target_os = "windows")]
-fn possible_game_data_locations() -> Vec<PathBuf> {
-  vec![
-    PathBuf::from("C:\\Program Files\\Steam\\steamapps\\common\\CrossCode"),
-    PathBuf::from(
-      "C:\\Program Files (x86)\\Steam\\steamapps\\common\\CrossCode",
-    ),
-  ]
+fn ask_to_choose_installation(
+  installations: Vec<DetectedInstallation>,
+) -> Option<PathBuf> {
+  let items: Vec<String> =
+    installations.iter().map(DetectedInstallation::describe).collect();
+  let index = native_ui::show_list_selection_dialog(
+    "Several CrossCode installations have been found. Which one should CCLoader be installed into?",
+    &items,
+  )?;
+  installations.into_iter().nth(index).map(|i| i.path)
 }
 
-fn ask_for_installation_confirmation(game_data_dir: &Path) -> bool {
+// returns whether the installation should go on
+fn check_game_compatibility(
+  game_data_dir: &Path,
+  release_info: &ReleaseInfo,
+) -> bool {
   use native_ui::*;
 
-  show_alert(AlertConfig {
-    icon: AlertIcon::Info,
-    title:
-      "In order to install CCLoader, this installer has to modify CC asset files. Do you want to continue? The installation process will take some time."
-        .to_owned(),
-    description: Some(format!(
-      "Path to the game data directory is {}",
+  // reading the nw.js binary takes a moment
+  let progress_window = Rc::new(ProgressWindow::new());
+  let compatibility = installer::check_game_compatibility(
+    game_data_dir,
+    release_info,
+    progress_window.clone(),
+  );
+  let was_cancelled = progress_window.is_cancelled();
+  drop(progress_window);
+  if was_cancelled {
+    info!("compatibility check was cancelled by the user");
+    return false;
+  }
+
+  match compatibility {
+    Compatibility::Compatible => true,
+    Compatibility::Unknown(reason) => {
+      show_alert(AlertConfig {
+        icon: AlertIcon::Warning,
+        title: format!(
+          "Couldn't check whether CCLoader v{} supports your version of CrossCode. Do you want to continue anyway?",
+          release_info.version
+        ),
+        description: Some(format!("Reason: {}", reason)),
+        buttons: AlertButtons::YesNo,
+      }) == Some(AlertResponse::Button1Pressed)
+    }
+    Compatibility::Incompatible(reason) => {
+      show_alert(AlertConfig {
+        icon: AlertIcon::Error,
+        title: format!("{}.", reason),
+        description: Some(
+          "Please, update the game before installing CCLoader.".to_owned(),
+        ),
+        buttons: AlertButtons::Ok,
+      });
+      false
+    }
+  }
+}
+
+// returns `None` if the user doesn't want to continue
+fn ask_for_installation_confirmation(
+  client: &mut HttpClient,
+  game_data_dir: &Path,
+) -> AppResult<Option<ReleaseChoice>> {
+  ask_for_release(
+    client,
+    &format!(
+      "In order to install CCLoader into {}, this installer has to modify CC asset files. The installation process will take some time. Which release do you want to install?",
       game_data_dir.display()
-    )),
-    buttons: AlertButtons::YesNo,
-  }) == Some(AlertResponse::Button1Pressed)
+    ),
+  )
 }
 
-#[derive(Debug)]
-struct ReleaseInfo {
-  download_url: Uri,
-  root_dir_path: PathBuf,
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ReleaseChoice {
+  Stable,
+  Testing,
+  // a specific version, e.g. an older one to reproduce a bug
+  Version(String),
 }
 
-fn fetch_latest_release_info(
+fn ask_for_release(
   client: &mut HttpClient,
-) -> AppResult<ReleaseInfo> {
-  let response = client
-    .send(HttpRequest::get(CCMODDB_DATA_URL).body(Vec::new()).unwrap())
-    .context("network error")?;
-
-  let status = response.status();
-  if !status.is_success() {
-    bail!("HTTP error: {}", status);
+  title: &str,
+) -> AppResult<Option<ReleaseChoice>> {
+  let index = match native_ui::show_list_selection_dialog(
+    title,
+    &[
+      "The latest stable release (recommended)".to_owned(),
+      "The latest testing release".to_owned(),
+      "An older release...".to_owned(),
+    ],
+  ) {
+    Some(index) => index,
+    None => return Ok(None),
+  };
+  match index {
+    0 => return Ok(Some(ReleaseChoice::Stable)),
+    1 => return Ok(Some(ReleaseChoice::Testing)),
+    _ => {}
   }
 
-  let release_data: JsonValue = serde_json::from_slice(&response.body())
-    .context("invalid response received from CCModDB")?;
-
-  try_ccmoddb_data_into_release_info(release_data)
-    .ok_or_else(|| err_msg("invalid JSON data received from CCModDB"))
+  let versions: Vec<String> = installer::fetch_ccloader_releases(client)
+    .context("Couldn't fetch the list of CCLoader releases")?
+    .into_iter()
+    .map(|release| release.version)
+    .collect();
+  if versions.is_empty() {
+    bail!("Couldn't find any CCLoader releases");
+  }
+  let items: Vec<String> =
+    versions.iter().map(|version| format!("CCLoader v{}", version)).collect();
+  Ok(
+    native_ui::show_list_selection_dialog(
+      "Which version of CCLoader do you want to install?",
+      &items,
+    )
+    .and_then(|index| versions.into_iter().nth(index))
+    .map(ReleaseChoice::Version),
+  )
 }
 
-fn try_ccmoddb_data_into_release_info(
-  mut data: JsonValue,
-) -> Option<ReleaseInfo> {
-  let package: &mut JsonValue = &mut data["ccloader"];
-  let artifacts: &mut Vec<JsonValue> =
-    package["installation"].as_array_mut()?;
-
-  const ZIP_FILE_EXT: &str = ".zip";
-  const TAR_GZ_FILE_EXT: &str = ".tar.gz";
-  let main_artifact: &mut JsonValue =
-    artifacts.iter_mut().find(|artifact| {
-      artifact["type"].as_str() == Some("modZip")
-        && artifact["source"]
-          .as_str()
-          .map_or(false, |s| s.starts_with("CCLoader-"))
-        && artifact["url"].as_str().map_or(false, |s| s.ends_with(ZIP_FILE_EXT))
-    })?;
-
-  fn into_string(value: JsonValue) -> Option<String> {
-    match value {
-      JsonValue::String(s) => Some(s),
-      _ => None,
-    }
+// offered when CCModDB can't be reached, e.g. on offline machines
+fn ask_for_local_archive(fetch_error: &str) -> Option<PathBuf> {
+  use native_ui::*;
+
+  let response = show_alert(AlertConfig {
+    icon: AlertIcon::Warning,
+    title: fetch_error.to_owned(),
+    description: Some(
+      "If this computer is offline, CCLoader can be installed from a release archive (e.g. CCLoader-2.22.1.tar.gz) downloaded on another machine. Do you want to choose an archive?"
+        .to_owned(),
+    ),
+    buttons: AlertButtons::YesNo,
+  });
+  if response != Some(AlertResponse::Button1Pressed) {
+    return None;
   }
-  let mut download_url = into_string(main_artifact["url"].take())?;
-  download_url.replace_range(
-    download_url.len() - ZIP_FILE_EXT.len()..download_url.len(),
-    TAR_GZ_FILE_EXT,
-  );
-  let root_dir_path = into_string(main_artifact["source"].take())?;
 
-  Some(ReleaseInfo {
-    download_url: Uri::try_from(download_url).ok()?,
-    root_dir_path: PathBuf::from(root_dir_path),
-  })
+  open_pick_file_dialog("CCLoader release archives", &["tar.gz", "zip"])
 }
 
-fn download_release_archive(
-  client: &mut HttpClient,
-  download_url: Uri,
-) -> AppResult<Body> {
-  let response = client
-    .send(HttpRequest::get(download_url).body(Vec::new()).unwrap())
-    .context("network error")?;
-
-  let status = response.status();
-  if !status.is_success() {
-    bail!("HTTP error: {}", status);
-  }
-
-  Ok(response.into_body())
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ExistingInstallationAction {
+  Update,
+  ChooseRelease,
+  Repair,
+  ManageMods,
+  Uninstall,
 }
 
-fn unpack_release_archive(
-  compressed_archive_data: Vec<u8>,
-  archive_root_dir_path: &Path,
+fn ask_what_to_do_with_existing_installation(
   game_data_dir: &Path,
-) -> AppResult<()> {
-  let mut decoder = GzDecoder::new(&compressed_archive_data[..]);
-  let mut archive = Archive::new(&mut decoder);
-  archive.set_preserve_permissions(true);
-
-  info!("unpacking the release archive to {}", game_data_dir.display());
-
-  let unpacked_temporary_dir = game_data_dir.join(archive_root_dir_path);
-  fs::create_dir_all(&unpacked_temporary_dir).with_context(|_| {
-    format!("couldn't create directory '{}'", archive_root_dir_path.display())
-  })?;
-
-  for entry in archive.entries().context("archive error")? {
-    let mut entry = entry.context("archive read I/O error")?;
-    if let Ok(entry_path) = entry.path() {
-      let entry_path: PathBuf = entry_path.into_owned();
-      if let Ok(rel_path) = entry_path.strip_prefix(archive_root_dir_path) {
-        if !(rel_path.starts_with(&*CCLOADER_DIR_PATH)
-          || rel_path.starts_with(&*MODS_DIR_PATH))
-        {
-          continue;
-        }
-
-        info!("unpacking {}", rel_path.display());
-        let was_unpacked: bool =
-          entry.unpack_in(game_data_dir).context("archive unpack I/O error")?;
-        if !was_unpacked {
-          continue;
-        }
-      }
-    }
-  }
+  installed_version: Option<&str>,
+) -> Option<ExistingInstallationAction> {
+  use native_ui::*;
 
-  let install = |rel_path: &Path| -> AppResult<()> {
-    info!("installing {}", rel_path.display());
-    fs::rename(
-      unpacked_temporary_dir.join(rel_path),
-      game_data_dir.join(rel_path),
-    )
-    .with_context(|_| format!("couldn't install '{}'", rel_path.display()))
+  let installed_version = match installed_version {
+    Some(v) => format!("CCLoader v{}", v),
+    None => "CCLoader of an unknown version".to_owned(),
   };
 
-  install(&*CCLOADER_DIR_PATH)?;
-
-  let mods_dir = game_data_dir.join(&*MODS_DIR_PATH);
-  fs::create_dir_all(&mods_dir).with_context(|_| {
-    format!("couldn't create directory '{}'", mods_dir.display())
-  })?;
-  for entry in fs::read_dir(unpacked_temporary_dir.join(&*MODS_DIR_PATH))
-    .context("couldn't get the contents of the built-in mods directory")?
-  {
-    let entry = entry
-      .context("couldn't get the contents of the built-in mods directory")?;
-    if let Ok(file_type) = entry.file_type() {
-      if file_type.is_dir() {
-        let rel_path = MODS_DIR_PATH.join(entry.file_name());
-        if !game_data_dir.join(&rel_path).is_dir() {
-          install(&rel_path)?;
-        } else {
-          warn!("{} has already been installed, skipping", rel_path.display());
-        }
-      }
-    }
+  let index = show_list_selection_dialog(
+    &format!(
+      "{} is already installed in the game data directory {}. What do you want to do?",
+      installed_version,
+      game_data_dir.display()
+    ),
+    &[
+      "Update (or reinstall) CCLoader".to_owned(),
+      "Install a different CCLoader release".to_owned(),
+      "Repair CCLoader (restore missing or changed files)".to_owned(),
+      "Enable or disable mods".to_owned(),
+      "Uninstall CCLoader".to_owned(),
+    ],
+  )?;
+  match index {
+    0 => Some(ExistingInstallationAction::Update),
+    1 => Some(ExistingInstallationAction::ChooseRelease),
+    2 => Some(ExistingInstallationAction::Repair),
+    3 => Some(ExistingInstallationAction::ManageMods),
+    _ => Some(ExistingInstallationAction::Uninstall),
   }
+}
 
-  fs::remove_dir_all(&unpacked_temporary_dir).with_context(|_| {
-    format!("couldn't delete directory '{}'", archive_root_dir_path.display())
-  })?;
+// `Some(false)` means that the user wants to see the other options instead
+fn ask_for_repatching_confirmation(
+  installed_version: Option<&str>,
+) -> Option<bool> {
+  use native_ui::*;
 
-  Ok(())
+  match show_alert(AlertConfig {
+    icon: AlertIcon::Warning,
+    title: format!(
+      "{} is installed, but the game doesn't start it anymore, most likely because the game has been updated by Steam. Do you want to fix that?",
+      match installed_version {
+        Some(v) => format!("CCLoader v{}", v),
+        None => "CCLoader of an unknown version".to_owned(),
+      },
+    ),
+    description: Some(
+      "Press 'Yes' to make the game start CCLoader again (nothing else will be changed) or 'No' to see the other options."
+        .to_owned(),
+    ),
+    buttons: AlertButtons::YesNoCancel,
+  }) {
+    Some(AlertResponse::Button1Pressed) => Some(true),
+    Some(AlertResponse::Button2Pressed) => Some(false),
+    _ => None,
+  }
 }
 
-fn patch_crosscode_assets(game_data_dir: &Path) -> AppResult<()> {
-  use std::fs::{File, OpenOptions};
-  use std::io::{Seek, SeekFrom};
-
-  let package_json_path = game_data_dir.join("package.json");
-  info!("patching {}", package_json_path.display());
-
-  let mut package_json_file: File = OpenOptions::new()
-    .create(false)
-    .read(true)
-    .write(true)
-    .open(package_json_path)
-    .context("couldn't open package.json")?;
-
-  let mut package_json_data: JsonValue =
-    serde_json::from_reader(&mut package_json_file)
-      .context("couldn't read package.json")?;
-  if !package_json_data.is_object() {
-    bail!("data in package.json is invalid");
+fn ask_for_builtin_mods_removal(game_data_dir: &Path) -> Option<bool> {
+  use native_ui::*;
+
+  let installed_builtin_mods: Vec<String> =
+    installer::installed_builtin_mod_names(
+      game_data_dir,
+      installer::read_install_manifest(game_data_dir).as_ref(),
+    );
+
+  if installed_builtin_mods.is_empty() {
+    return match show_alert(AlertConfig {
+      icon: AlertIcon::Warning,
+      title: "Are you sure you want to uninstall CCLoader? Mods won't be loaded anymore.".to_owned(),
+      description: None,
+      buttons: AlertButtons::YesNo,
+    }) {
+      Some(AlertResponse::Button1Pressed) => Some(false),
+      _ => None,
+    };
   }
 
-  package_json_data["main"] = JsonValue::String(format!(
-    "{}/index.html",
-    // unwrap is used because a) this is a compile time constant, so I can
-    // guarantee that it's properly encoded because b) JSON strings can contain
-    // only valid Unicode characters
-    CCLOADER_DIR_PATH.to_str().unwrap()
-  ));
+  match show_alert(AlertConfig {
+    icon: AlertIcon::Warning,
+    title: "Do you want to delete the mods bundled with CCLoader as well? Press 'Yes' to delete them and 'No' to keep them. Other mods will be left untouched in either case.".to_owned(),
+    description: Some(format!(
+      "The following built-in mods will be deleted: {}",
+      installed_builtin_mods.join(", "),
+    )),
+    buttons: AlertButtons::YesNoCancel,
+  }) {
+    Some(AlertResponse::Button1Pressed) => Some(true),
+    Some(AlertResponse::Button2Pressed) => Some(false),
+    _ => None,
+  }
+}
 
-  // truncate the file, then overwrite it with the patched data
+fn ask_for_update_confirmation(
+  game_data_dir: &Path,
+  installed_version: Option<&str>,
+  latest_version: &str,
+  // `false` when the user has chosen a testing or an older release
+  is_latest_stable: bool,
+) -> bool {
+  use native_ui::*;
 
-  // set_len can return an error only if the file isn't opened for writing, or
-  // the desired length would cause an integer overflow
-  package_json_file.set_len(0).unwrap();
-  // seek can fail only when called with a negative offset
-  package_json_file.seek(SeekFrom::Start(0)).unwrap();
+  let title = match installed_version {
+    _ if !is_latest_stable => format!(
+      "{} is installed. Do you want to replace it with CCLoader v{}? The built-in mods will be replaced as well, other mods will be left untouched.",
+      match installed_version {
+        Some(v) => format!("CCLoader v{}", v),
+        None => "CCLoader of an unknown version".to_owned(),
+      },
+      latest_version,
+    ),
+    Some(installed_version)
+      if !installer::is_newer_version(installed_version, latest_version) =>
+    {
+      format!(
+        "CCLoader v{} is already installed and is up to date (the latest version is v{}). Do you want to reinstall it? The built-in mods will be reinstalled as well, other mods will be left untouched.",
+        installed_version, latest_version,
+      )
+    }
+    Some(installed_version) => format!(
+      "CCLoader v{} is installed, the latest version is v{}. Do you want to update it? The built-in mods will be updated as well, other mods will be left untouched.",
+      installed_version, latest_version,
+    ),
+    None => format!(
+      "An existing CCLoader installation of an unknown version was found, the latest version is v{}. Do you want to replace it? The built-in mods will be updated as well, other mods will be left untouched.",
+      latest_version,
+    ),
+  };
 
-  serde_json::to_writer_pretty(&mut package_json_file, &package_json_data)
-    .context("couldn't write patched package.json")?;
+  show_alert(AlertConfig {
+    icon: AlertIcon::Info,
+    title,
+    description: Some(format!(
+      "Path to the game data directory is {}",
+      game_data_dir.display()
+    )),
+    buttons: AlertButtons::YesNo,
+  }) == Some(AlertResponse::Button1Pressed)
+}
 
-  Ok(())
+fn show_uninstallation_success_alert() {
+  use native_ui::*;
+  show_alert(AlertConfig {
+    icon: AlertIcon::Info,
+    title: "CCLoader has been successfully uninstalled!".to_owned(),
+    description: Some(
+      "The game will now start without any mods. You can install CCLoader again at any time."
+        .to_owned(),
+    ),
+    buttons: AlertButtons::Ok,
+  });
 }
 
-fn show_installation_success_alert(game_data_dir: &Path) {
+fn show_installation_success_alert(game_data_dir: &Path, mode: InstallMode) {
   use native_ui::*;
   if let Some(AlertResponse::Button1Pressed) = show_alert(AlertConfig {
     icon: AlertIcon::Info,
-    title: "CCLoader has been successfully installed!".to_owned(),
+    title: match mode {
+      InstallMode::FreshInstall => "CCLoader has been successfully installed!",
+      InstallMode::Update => "CCLoader has been successfully updated!",
+    }
+    .to_owned(),
     description: Some("Open the mods directory?".to_owned()),
     buttons: AlertButtons::YesNo,
   }) {
-    open_path(&game_data_dir.join(&*MODS_DIR_PATH))
+    open_path(&game_data_dir.join(*MODS_DIR_PATH))
   }
 }
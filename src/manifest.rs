@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use log::info;
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::{err_msg, Result as AppResult, ResultExt};
+
+pub const MANIFEST_FILE_NAME: &str = "ccloader-installer-manifest.json";
+pub const PACKAGE_JSON_BACKUP_FILE_NAME: &str = "package.json.orig";
+pub const MODS_MANIFEST_FILE_NAME: &str = "ccloader-installer-mods.json";
+
+// bumped whenever the format changes in a backwards-incompatible way
+const MANIFEST_FORMAT_VERSION: u64 = 1;
+
+#[derive(Debug, Clone)]
+pub struct InstallManifest {
+  pub installer_version: String,
+  pub ccloader_version: String,
+  pub source_url: String,
+  pub original_main: String,
+  pub builtin_mods: Vec<String>,
+  // paths relative to the game data directory, always separated with forward
+  // slashes regardless of the OS
+  pub files: Vec<String>,
+  // SHA-256 checksums of `files`, manifests written by older versions of the
+  // installer don't have them
+  pub checksums: BTreeMap<String, String>,
+}
+
+impl InstallManifest {
+  pub fn read(game_data_dir: &Path) -> AppResult<Option<Self>> {
+    let path = game_data_dir.join(MANIFEST_FILE_NAME);
+    let bytes = match fs::read(&path) {
+      Ok(bytes) => bytes,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => {
+        return Err(e).context("couldn't read the installation manifest")
+      }
+    };
+
+    let data: JsonValue = serde_json::from_slice(&bytes)
+      .context("couldn't parse the installation manifest")?;
+    match Self::from_json(&data) {
+      Some(manifest) => Ok(Some(manifest)),
+      None => Err(err_msg("data in the installation manifest is invalid")),
+    }
+  }
+
+  pub fn write(&self, game_data_dir: &Path) -> AppResult<()> {
+    let path = game_data_dir.join(MANIFEST_FILE_NAME);
+    info!("writing the installation manifest to {}", path.display());
+    let bytes = serde_json::to_vec_pretty(&self.to_json()).unwrap();
+    fs::write(&path, bytes).context("couldn't write the installation manifest")
+  }
+
+  fn to_json(&self) -> JsonValue {
+    json!({
+      "format_version": MANIFEST_FORMAT_VERSION,
+      "installer_version": self.installer_version,
+      "ccloader_version": self.ccloader_version,
+      "source_url": self.source_url,
+      "original_main": self.original_main,
+      "package_json_backup": PACKAGE_JSON_BACKUP_FILE_NAME,
+      "builtin_mods": self.builtin_mods,
+      "files": self.files,
+      "checksums": self.checksums,
+    })
+  }
+
+  fn from_json(data: &JsonValue) -> Option<Self> {
+    if data["format_version"].as_u64()? != MANIFEST_FORMAT_VERSION {
+      return None;
+    }
+
+    fn string_array(value: &JsonValue) -> Option<Vec<String>> {
+      value.as_array()?.iter().map(|s| s.as_str().map(str::to_owned)).collect()
+    }
+
+    Some(Self {
+      installer_version: data["installer_version"].as_str()?.to_owned(),
+      ccloader_version: data["ccloader_version"].as_str()?.to_owned(),
+      source_url: data["source_url"].as_str()?.to_owned(),
+      original_main: data["original_main"].as_str()?.to_owned(),
+      builtin_mods: string_array(&data["builtin_mods"])?,
+      files: string_array(&data["files"])?,
+      checksums: match &data["checksums"] {
+        JsonValue::Null => BTreeMap::new(),
+        checksums => checksums
+          .as_object()?
+          .iter()
+          .map(|(path, hash)| Some((path.clone(), hash.as_str()?.to_owned())))
+          .collect::<Option<BTreeMap<String, String>>>()?,
+      },
+    })
+  }
+}
+
+// Remembers which files the mods installed from CCModDB consisted of, so that
+// local modifications can be detected before they are updated.
+#[derive(Debug, Clone)]
+pub struct ModRecord {
+  pub name: String,
+  pub version: String,
+  // paths relative to the directory of the mod mapped to their SHA-256
+  // checksums, the paths are formatted like in `InstallManifest::files`
+  pub files: BTreeMap<String, String>,
+}
+
+pub fn read_mod_records(game_data_dir: &Path) -> AppResult<Vec<ModRecord>> {
+  let path = game_data_dir.join(MODS_MANIFEST_FILE_NAME);
+  let bytes = match fs::read(&path) {
+    Ok(bytes) => bytes,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(e).context("couldn't read the mods manifest"),
+  };
+
+  let data: JsonValue = serde_json::from_slice(&bytes)
+    .context("couldn't parse the mods manifest")?;
+  mod_records_from_json(&data)
+    .ok_or_else(|| err_msg("data in the mods manifest is invalid"))
+}
+
+pub fn write_mod_records(
+  game_data_dir: &Path,
+  records: &[ModRecord],
+) -> AppResult<()> {
+  let path = game_data_dir.join(MODS_MANIFEST_FILE_NAME);
+  info!("writing the mods manifest to {}", path.display());
+  let data = json!({
+    "format_version": MANIFEST_FORMAT_VERSION,
+    "mods": records
+      .iter()
+      .map(|record| {
+        json!({
+          "name": record.name,
+          "version": record.version,
+          "files": record.files,
+        })
+      })
+      .collect::<Vec<JsonValue>>(),
+  });
+  let bytes = serde_json::to_vec_pretty(&data).unwrap();
+  fs::write(&path, bytes).context("couldn't write the mods manifest")
+}
+
+fn mod_records_from_json(data: &JsonValue) -> Option<Vec<ModRecord>> {
+  if data["format_version"].as_u64()? != MANIFEST_FORMAT_VERSION {
+    return None;
+  }
+
+  data["mods"]
+    .as_array()?
+    .iter()
+    .map(|record| {
+      Some(ModRecord {
+        name: record["name"].as_str()?.to_owned(),
+        version: record["version"].as_str()?.to_owned(),
+        files: record["files"]
+          .as_object()?
+          .iter()
+          .map(|(path, hash)| Some((path.clone(), hash.as_str()?.to_owned())))
+          .collect::<Option<BTreeMap<String, String>>>()?,
+      })
+    })
+    .collect()
+}
+
+pub fn list_files_recursively(
+  game_data_dir: &Path,
+  rel_dir_path: &Path,
+) -> AppResult<Vec<String>> {
+  let mut result = Vec::new();
+  let mut dirs_stack: Vec<PathBuf> = vec![rel_dir_path.to_owned()];
+  while let Some(rel_dir_path) = dirs_stack.pop() {
+    let read_dir_error_context =
+      || format!("couldn't get the contents of '{}'", rel_dir_path.display());
+    for entry in fs::read_dir(game_data_dir.join(&rel_dir_path))
+      .with_context(|_| read_dir_error_context())?
+    {
+      let entry = entry.with_context(|_| read_dir_error_context())?;
+      let rel_path = rel_dir_path.join(entry.file_name());
+      let file_type =
+        entry.file_type().with_context(|_| read_dir_error_context())?;
+      if file_type.is_dir() {
+        dirs_stack.push(rel_path);
+      } else if let Some(s) = path_to_manifest_string(&rel_path) {
+        result.push(s);
+      }
+    }
+  }
+  result.sort();
+  Ok(result)
+}
+
+pub fn path_to_manifest_string(path: &Path) -> Option<String> {
+  let mut result = String::new();
+  for component in path.components() {
+    match component {
+      Component::Normal(s) => {
+        if !result.is_empty() {
+          result.push('/');
+        }
+        result.push_str(s.to_str()?);
+      }
+      // only relative paths without any `..` can be recorded
+      _ => return None,
+    }
+  }
+  Some(result)
+}
@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde_json::Value as JsonValue;
+
+use crate::archive;
+use crate::ccmoddb::{Artifact, Package};
+use crate::error::{Result as AppResult, ResultExt};
+use crate::http_client::HttpClient;
+use crate::installer::{self, MODS_DIR_PATH};
+use crate::manifest::{self, ModRecord};
+use crate::progress::Progress;
+use crate::transaction::Transaction;
+
+// mods describe themselves either with the newer ccmod.json or with
+// package.json, the former takes precedence when both are present
+const CCMOD_JSON_FILE_NAME: &str = "ccmod.json";
+const PACKAGE_JSON_FILE_NAME: &str = "package.json";
+
+lazy_static! {
+  // CCLoader remembers which mods are disabled only in the localStorage of the
+  // game, which can't be edited from the outside, so disabled mods are moved
+  // out of its reach into this directory instead
+  pub static ref DISABLED_MODS_DIR_PATH: &'static Path =
+    Path::new("assets/disabled-mods");
+  // mods replaced by newer versions are kept here
+  pub static ref MOD_BACKUPS_DIR_PATH: &'static Path =
+    Path::new("ccloader-installer-backups/mods");
+}
+
+#[derive(Debug, Clone)]
+pub struct InstalledMod {
+  // the name of the directory inside of `assets/mods` (or of the directory for
+  // disabled mods), which doesn't have to match the name of the mod
+  pub dir_name: String,
+  pub name: String,
+  pub version: Option<String>,
+  pub enabled: bool,
+}
+
+impl InstalledMod {
+  // relative to the game data directory
+  pub fn rel_path(&self) -> PathBuf {
+    let mods_dir_path: &Path =
+      if self.enabled { &MODS_DIR_PATH } else { &DISABLED_MODS_DIR_PATH };
+    mods_dir_path.join(&self.dir_name)
+  }
+}
+
+// Lists the mods in `assets/mods` and the disabled ones, directories without a
+// valid metadata file are skipped because CCLoader doesn't load them either.
+pub fn installed_mods(game_data_dir: &Path) -> AppResult<Vec<InstalledMod>> {
+  let mut result = Vec::new();
+  for &(mods_dir_path, enabled) in
+    &[(*MODS_DIR_PATH, true), (*DISABLED_MODS_DIR_PATH, false)]
+  {
+    let mods_dir = game_data_dir.join(mods_dir_path);
+    if !mods_dir.is_dir() {
+      continue;
+    }
+
+    for entry in fs::read_dir(&mods_dir).with_context(|_| {
+      format!("couldn't get the contents of '{}'", mods_dir_path.display())
+    })? {
+      let entry = entry.with_context(|_| {
+        format!("couldn't get the contents of '{}'", mods_dir_path.display())
+      })?;
+      if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+        continue;
+      }
+      let dir_name = match entry.file_name().into_string() {
+        Ok(dir_name) => dir_name,
+        Err(_) => continue,
+      };
+      match read_mod_metadata(&entry.path()) {
+        Some((name, version)) => {
+          result.push(InstalledMod { dir_name, name, version, enabled })
+        }
+        None => {
+          warn!("{} doesn't contain a valid mod", entry.path().display())
+        }
+      }
+    }
+  }
+
+  result.sort_by(|a, b| {
+    a.name.cmp(&b.name).then_with(|| a.dir_name.cmp(&b.dir_name))
+  });
+  Ok(result)
+}
+
+// Moves a mod between `assets/mods` and the directory for disabled mods.
+pub fn set_mod_enabled(
+  game_data_dir: &Path,
+  installed_mod: &InstalledMod,
+  enabled: bool,
+) -> AppResult<()> {
+  if installed_mod.enabled == enabled {
+    return Ok(());
+  }
+
+  let rel_path = installed_mod.rel_path();
+  let new_mods_dir_path: &Path =
+    if enabled { &MODS_DIR_PATH } else { &DISABLED_MODS_DIR_PATH };
+  let new_rel_path = new_mods_dir_path.join(&installed_mod.dir_name);
+  if game_data_dir.join(&new_rel_path).exists() {
+    bail!(
+      "Couldn't move {} to '{}' because it already exists",
+      installed_mod.name,
+      new_rel_path.display()
+    );
+  }
+
+  info!("moving {} to {}", rel_path.display(), new_rel_path.display());
+  // a single rename is atomic, so there is no need for a transaction here
+  fs::create_dir_all(game_data_dir.join(new_mods_dir_path)).with_context(
+    |_| format!("couldn't create directory '{}'", new_mods_dir_path.display()),
+  )?;
+  fs::rename(game_data_dir.join(&rel_path), game_data_dir.join(&new_rel_path))
+    .with_context(|_| {
+      format!(
+        "Couldn't {} {}",
+        if enabled { "enable" } else { "disable" },
+        installed_mod.name
+      )
+    })
+}
+
+// returns the name and the version of the mod
+fn read_mod_metadata(mod_dir: &Path) -> Option<(String, Option<String>)> {
+  for (file_name, name_key) in
+    &[(CCMOD_JSON_FILE_NAME, "id"), (PACKAGE_JSON_FILE_NAME, "name")]
+  {
+    let bytes = match fs::read(mod_dir.join(file_name)) {
+      Ok(bytes) => bytes,
+      Err(_) => continue,
+    };
+    let data: JsonValue = serde_json::from_slice(&bytes).ok()?;
+    let name = data[*name_key].as_str()?.to_owned();
+    let version = data["version"].as_str().map(|s| s.to_owned());
+    return Some((name, version));
+  }
+  None
+}
+
+// Checks whether the files of an installed mod differ from the ones it was
+// installed with. Mods which weren't installed by the installer can't be
+// checked, unless they are git working copies, which are assumed to be
+// modified.
+pub fn is_locally_modified(
+  game_data_dir: &Path,
+  installed_mod: &InstalledMod,
+  records: &[ModRecord],
+) -> AppResult<bool> {
+  let mod_dir = game_data_dir.join(installed_mod.rel_path());
+  let record = records.iter().find(|record| {
+    record.name == installed_mod.name
+      && installed_mod.version.as_ref() == Some(&record.version)
+  });
+  match record {
+    Some(record) => Ok(file_checksums(&mod_dir)? != record.files),
+    None => Ok(mod_dir.join(".git").exists()),
+  }
+}
+
+fn file_checksums(dir: &Path) -> AppResult<BTreeMap<String, String>> {
+  let mut result = BTreeMap::new();
+  for file in manifest::list_files_recursively(dir, Path::new(""))? {
+    let checksum = installer::sha256_file(&dir.join(&file))
+      .with_context(|_| format!("couldn't read '{}'", file))?;
+    result.insert(file, checksum);
+  }
+  Ok(result)
+}
+
+// Downloads the mod artifact of a CCModDB package and unpacks it to
+// `rel_path`. Whatever is there is moved into the backups directory, the path
+// of the backup is returned.
+pub fn install_mod(
+  client: &mut HttpClient,
+  game_data_dir: &Path,
+  package: &Package,
+  rel_path: &Path,
+  progress: Rc<dyn Progress>,
+) -> AppResult<Option<PathBuf>> {
+  let artifact = package.mod_artifact().ok_or_else(|| {
+    format!("{} doesn't have any installable archive in CCModDB", package.name)
+  })?;
+
+  progress.set_status(&format!(
+    "Downloading {} v{}...",
+    package.name, package.version
+  ));
+  let archive = installer::download_archive(
+    client,
+    &artifact.url,
+    artifact.sha256.as_deref(),
+    progress.clone(),
+  )
+  .with_context(|_| format!("Couldn't download {}", package.name))?;
+
+  let result = install_mod_archive(
+    game_data_dir,
+    package,
+    artifact,
+    &archive.path,
+    rel_path,
+    progress,
+  );
+
+  installer::remove_downloaded_archive(&archive);
+
+  result
+}
+
+fn install_mod_archive(
+  game_data_dir: &Path,
+  package: &Package,
+  artifact: &Artifact,
+  archive_path: &Path,
+  rel_path: &Path,
+  progress: Rc<dyn Progress>,
+) -> AppResult<Option<PathBuf>> {
+  let mut transaction = Transaction::begin(game_data_dir)
+    .context("Couldn't prepare the installation")?;
+
+  progress.set_status(&format!(
+    "Installing {} v{}...",
+    package.name, package.version
+  ));
+  progress.set_fraction(Some(0.0));
+
+  let unpack_dir = transaction.staging_dir().join("unpacked");
+  info!("unpacking {} to {}", archive_path.display(), unpack_dir.display());
+  let source_dir_path = Path::new(artifact.source.as_deref().unwrap_or(""));
+  archive::unpack_archive(
+    archive_path,
+    &unpack_dir,
+    progress.clone(),
+    &|entry_path| entry_path.starts_with(source_dir_path),
+  )
+  .with_context(|_| format!("Couldn't unpack {}", archive_path.display()))?;
+
+  if progress.is_cancelled() {
+    bail!("cancelled by the user");
+  }
+
+  let unpacked_mod_dir = unpack_dir.join(source_dir_path);
+  if !unpacked_mod_dir.is_dir() {
+    bail!(
+      "'{}' doesn't contain the directory '{}'",
+      archive_path.display(),
+      source_dir_path.display()
+    );
+  }
+
+  let backup_rel_path = if game_data_dir.join(rel_path).exists() {
+    let backup_rel_path = MOD_BACKUPS_DIR_PATH.join(format!(
+      "{}-{}",
+      rel_path.file_name().and_then(|s| s.to_str()).unwrap_or(&package.name),
+      // the format can't be invalid
+      time::now().strftime("%Y%m%d-%H%M%S").unwrap()
+    ));
+    transaction
+      .back_up(rel_path, &backup_rel_path)
+      .context("Couldn't back up the previous version")?;
+    Some(backup_rel_path)
+  } else {
+    None
+  };
+
+  if let Some(parent) = rel_path.parent() {
+    transaction.create_dir_all(parent)?;
+  }
+  transaction
+    .install(&unpacked_mod_dir, rel_path)
+    .with_context(|_| format!("Couldn't install {}", package.name))?;
+
+  let files = file_checksums(&game_data_dir.join(rel_path))?;
+  transaction
+    .snapshot_file(Path::new(manifest::MODS_MANIFEST_FILE_NAME))
+    .and_then(|()| {
+      let mut records = manifest::read_mod_records(game_data_dir)?;
+      records.retain(|record| record.name != package.name);
+      records.push(ModRecord {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        files,
+      });
+      records.sort_by(|a, b| a.name.cmp(&b.name));
+      manifest::write_mod_records(game_data_dir, &records)
+    })
+    .context("Couldn't record the installed files")?;
+
+  transaction.commit();
+
+  Ok(backup_rel_path)
+}
@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::convert::TryInto;
 use std::ffi::{CStr, CString, OsString};
 use std::mem;
@@ -92,12 +93,29 @@ pub fn show_alert(config: AlertConfig) -> Option<AlertResponse> {
 }
 
 pub fn open_pick_folder_dialog() -> Option<PathBuf> {
+  run_file_chooser_dialog(GTK_FILE_CHOOSER_ACTION_SELECT_FOLDER, None)
+}
+
+pub fn open_pick_file_dialog(
+  filter_name: &str,
+  extensions: &[&str],
+) -> Option<PathBuf> {
+  run_file_chooser_dialog(
+    GTK_FILE_CHOOSER_ACTION_OPEN,
+    Some((filter_name, extensions)),
+  )
+}
+
+fn run_file_chooser_dialog(
+  action: GtkFileChooserAction,
+  filter: Option<(&str, &[&str])>,
+) -> Option<PathBuf> {
   unsafe {
     let chooser: *mut GtkWidget = {
       gtk_file_chooser_dialog_new(
         null::<c_char>(),
         null_mut::<GtkWindow>(),
-        GTK_FILE_CHOOSER_ACTION_SELECT_FOLDER,
+        action,
         b"_Cancel\0".as_ptr() as *const c_char,
         GTK_RESPONSE_CANCEL,
         b"_Open\0".as_ptr() as *const c_char,
@@ -106,6 +124,18 @@ pub fn open_pick_folder_dialog() -> Option<PathBuf> {
       )
     };
 
+    if let Some((filter_name, extensions)) = filter {
+      let file_filter: *mut GtkFileFilter = gtk_file_filter_new();
+      let filter_name = CString::new(filter_name).unwrap();
+      gtk_file_filter_set_name(file_filter, filter_name.as_ptr());
+      for extension in extensions {
+        let pattern = CString::new(format!("*.{}", extension)).unwrap();
+        gtk_file_filter_add_pattern(file_filter, pattern.as_ptr());
+      }
+      // the chooser takes ownership of the filter
+      gtk_file_chooser_add_filter(chooser as *mut GtkFileChooser, file_filter);
+    }
+
     let mut result = None;
 
     let response: GtkResponseType = gtk_dialog_run(chooser as *mut GtkDialog);
@@ -125,6 +155,110 @@ pub fn open_pick_folder_dialog() -> Option<PathBuf> {
   }
 }
 
+const MAX_VISIBLE_CHOICE_ITEMS: usize = 16;
+
+pub fn show_choice_dialog(
+  title: &str,
+  items: &[String],
+  multiple: bool,
+  checked: &[bool],
+) -> Option<Vec<bool>> {
+  unsafe {
+    let dialog: *mut GtkWidget = gtk_dialog_new();
+    let window_title = CString::new(crate::PKG_NAME).unwrap();
+    gtk_window_set_title(dialog as *mut GtkWindow, window_title.as_ptr());
+    gtk_window_set_resizable(dialog as *mut GtkWindow, GFALSE);
+    gtk_window_set_position(dialog as *mut GtkWindow, GTK_WIN_POS_CENTER);
+
+    let content_area: *mut GtkBox =
+      gtk_dialog_get_content_area(dialog as *mut GtkDialog);
+    gtk_box_set_spacing(content_area, 6);
+    gtk_container_set_border_width(content_area as *mut GtkContainer, 12);
+
+    let title = CString::new(title).unwrap();
+    let label: *mut GtkWidget = gtk_label_new(title.as_ptr());
+    gtk_label_set_line_wrap(label as *mut GtkLabel, GTRUE);
+    gtk_label_set_max_width_chars(label as *mut GtkLabel, 60);
+    gtk_widget_set_halign(label, GTK_ALIGN_START);
+    gtk_box_pack_start(content_area, label, GFALSE, GFALSE, 6);
+
+    // long lists (e.g. of mods) are put into a scrollable area to fit on the
+    // screen
+    let list_box: *mut GtkWidget = gtk_box_new(GTK_ORIENTATION_VERTICAL, 0);
+    if items.len() > MAX_VISIBLE_CHOICE_ITEMS {
+      let scrolled_window: *mut GtkWidget =
+        gtk_scrolled_window_new(null_mut(), null_mut());
+      gtk_scrolled_window_set_policy(
+        scrolled_window as *mut GtkScrolledWindow,
+        GTK_POLICY_NEVER,
+        GTK_POLICY_AUTOMATIC,
+      );
+      gtk_scrolled_window_set_min_content_height(
+        scrolled_window as *mut GtkScrolledWindow,
+        400,
+      );
+      gtk_container_add(scrolled_window as *mut GtkContainer, list_box);
+      gtk_box_pack_start(content_area, scrolled_window, GTRUE, GTRUE, 0);
+    } else {
+      gtk_box_pack_start(content_area, list_box, GFALSE, GFALSE, 0);
+    }
+
+    let mut buttons: Vec<*mut GtkWidget> = Vec::with_capacity(items.len());
+    for (item, &is_checked) in items.iter().zip(checked) {
+      let item = CString::new(item.as_str()).unwrap();
+      let button: *mut GtkWidget = if multiple {
+        gtk_check_button_new_with_label(item.as_ptr())
+      } else {
+        // radio buttons are grouped with the first one
+        let group_member = buttons.first().copied().unwrap_or(null_mut());
+        gtk_radio_button_new_with_label_from_widget(
+          group_member as *mut GtkRadioButton,
+          item.as_ptr(),
+        )
+      };
+      gtk_toggle_button_set_active(
+        button as *mut GtkToggleButton,
+        if is_checked { GTRUE } else { GFALSE },
+      );
+      gtk_box_pack_start(list_box as *mut GtkBox, button, GFALSE, GFALSE, 0);
+      buttons.push(button);
+    }
+
+    gtk_dialog_add_button(
+      dialog as *mut GtkDialog,
+      b"_Cancel\0".as_ptr() as *const c_char,
+      GTK_RESPONSE_CANCEL,
+    );
+    gtk_dialog_add_button(
+      dialog as *mut GtkDialog,
+      b"_OK\0".as_ptr() as *const c_char,
+      GTK_RESPONSE_OK,
+    );
+    gtk_dialog_set_default_response(dialog as *mut GtkDialog, GTK_RESPONSE_OK);
+
+    gtk_widget_show_all(dialog);
+    let response: GtkResponseType = gtk_dialog_run(dialog as *mut GtkDialog);
+
+    let result = if response == GTK_RESPONSE_OK {
+      Some(
+        buttons
+          .iter()
+          .map(|&button| {
+            gtk_toggle_button_get_active(button as *mut GtkToggleButton)
+              != GFALSE
+          })
+          .collect(),
+      )
+    } else {
+      None
+    };
+
+    gtk_widget_destroy(dialog);
+
+    result
+  }
+}
+
 #[allow(clippy::while_immutable_condition)]
 pub fn open_path(path: &Path) {
   unsafe {
@@ -182,3 +316,136 @@ unsafe fn check_error(error: *mut GError) {
   // future.
   g_error_free(error);
 }
+
+pub struct ProgressWindow {
+  window: *mut GtkWidget,
+  label: *mut GtkWidget,
+  progress_bar: *mut GtkWidget,
+  // boxed so that its address, which is given to the signal handlers, stays
+  // the same when the struct is moved
+  cancelled: Box<Cell<bool>>,
+}
+
+impl ProgressWindow {
+  pub fn new() -> Self {
+    unsafe {
+      let window: *mut GtkWidget = gtk_window_new(GTK_WINDOW_TOPLEVEL);
+      let title = CString::new(crate::PKG_NAME).unwrap();
+      gtk_window_set_title(window as *mut GtkWindow, title.as_ptr());
+      gtk_window_set_default_size(window as *mut GtkWindow, 400, -1);
+      gtk_window_set_resizable(window as *mut GtkWindow, GFALSE);
+      gtk_window_set_position(window as *mut GtkWindow, GTK_WIN_POS_CENTER);
+      gtk_container_set_border_width(window as *mut GtkContainer, 12);
+
+      let vbox: *mut GtkWidget = gtk_box_new(GTK_ORIENTATION_VERTICAL, 12);
+      gtk_container_add(window as *mut GtkContainer, vbox);
+
+      let label: *mut GtkWidget = gtk_label_new(null());
+      gtk_widget_set_halign(label, GTK_ALIGN_START);
+      gtk_box_pack_start(vbox as *mut GtkBox, label, GFALSE, GFALSE, 0);
+
+      let progress_bar: *mut GtkWidget = gtk_progress_bar_new();
+      gtk_box_pack_start(vbox as *mut GtkBox, progress_bar, GFALSE, GFALSE, 0);
+
+      let cancel_button: *mut GtkWidget =
+        gtk_button_new_with_mnemonic(b"_Cancel\0".as_ptr() as *const c_char);
+      gtk_widget_set_halign(cancel_button, GTK_ALIGN_END);
+      gtk_box_pack_start(vbox as *mut GtkBox, cancel_button, GFALSE, GFALSE, 0);
+
+      let cancelled = Box::new(Cell::new(false));
+      let cancelled_ptr = &*cancelled as *const Cell<bool> as gpointer;
+
+      unsafe extern "C" fn on_cancel_clicked(
+        button: *mut GtkWidget,
+        user_data: gpointer,
+      ) {
+        (*(user_data as *const Cell<bool>)).set(true);
+        gtk_widget_set_sensitive(button, GFALSE);
+      }
+      // closing the window is treated just like pressing the cancel button,
+      // but the window itself stays until the operation is actually stopped
+      unsafe extern "C" fn on_delete_event(
+        _window: *mut GtkWidget,
+        _event: gpointer,
+        user_data: gpointer,
+      ) -> gboolean {
+        (*(user_data as *const Cell<bool>)).set(true);
+        GTRUE
+      }
+
+      // GCallback is a generic function pointer type, the actual signature
+      // depends on the signal
+      g_signal_connect_data(
+        cancel_button as *mut GObject,
+        b"clicked\0".as_ptr() as *const c_char,
+        Some(mem::transmute::<
+          unsafe extern "C" fn(*mut GtkWidget, gpointer),
+          unsafe extern "C" fn(),
+        >(on_cancel_clicked)),
+        cancelled_ptr,
+        None,
+        0,
+      );
+      g_signal_connect_data(
+        window as *mut GObject,
+        b"delete-event\0".as_ptr() as *const c_char,
+        Some(mem::transmute::<
+          unsafe extern "C" fn(*mut GtkWidget, gpointer, gpointer) -> gboolean,
+          unsafe extern "C" fn(),
+        >(on_delete_event)),
+        cancelled_ptr,
+        None,
+        0,
+      );
+
+      gtk_widget_show_all(window);
+
+      let this = Self { window, label, progress_bar, cancelled };
+      this.process_events();
+      this
+    }
+  }
+
+  pub fn set_text(&self, text: &str) {
+    let text = CString::new(text).unwrap();
+    unsafe {
+      gtk_label_set_text(self.label as *mut GtkLabel, text.as_ptr());
+    }
+    self.process_events();
+  }
+
+  pub fn set_fraction(&self, fraction: Option<f64>) {
+    let progress_bar = self.progress_bar as *mut GtkProgressBar;
+    unsafe {
+      match fraction {
+        Some(fraction) => {
+          gtk_progress_bar_set_fraction(progress_bar, fraction.clamp(0.0, 1.0))
+        }
+        None => gtk_progress_bar_pulse(progress_bar),
+      }
+    }
+    self.process_events();
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.process_events();
+    self.cancelled.get()
+  }
+
+  fn process_events(&self) {
+    unsafe {
+      while gtk_events_pending() != GFALSE {
+        gtk_main_iteration_do(GFALSE);
+      }
+    }
+  }
+}
+
+impl Drop for ProgressWindow {
+  fn drop(&mut self) {
+    unsafe {
+      gtk_widget_destroy(self.window);
+    }
+    self.process_events();
+  }
+}
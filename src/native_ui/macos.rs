@@ -12,7 +12,10 @@ use cocoa::appkit::{
   NSApplicationActivationPolicyRegular, NSRunningApplication,
 };
 use cocoa::base::{id, nil};
-use cocoa::foundation::{NSAutoreleasePool, NSString, NSURL};
+use cocoa::foundation::{
+  NSArray, NSAutoreleasePool, NSDefaultRunLoopMode, NSInteger, NSPoint, NSRect,
+  NSSize, NSString, NSUInteger, NSURL,
+};
 use objc::runtime::{NO, YES};
 use objc::{class, msg_send, sel, sel_impl};
 
@@ -112,14 +115,41 @@ pub fn show_alert(config: AlertConfig) -> Option<AlertResponse> {
 }
 
 pub fn open_pick_folder_dialog() -> Option<PathBuf> {
+  run_open_panel(None)
+}
+
+pub fn open_pick_file_dialog(
+  _filter_name: &str,
+  extensions: &[&str],
+) -> Option<PathBuf> {
+  run_open_panel(Some(extensions))
+}
+
+fn run_open_panel(file_extensions: Option<&[&str]>) -> Option<PathBuf> {
   request_focus();
 
   autorelease(|| unsafe {
     let dialog: id = msg_send![class!(NSOpenPanel), openPanel];
     let _: () = msg_send![dialog, setAllowsMultipleSelection: NO];
-    let _: () = msg_send![dialog, setCanChooseDirectories: YES];
-    let _: () = msg_send![dialog, setCanCreateDirectories: YES];
-    let _: () = msg_send![dialog, setCanChooseFiles: NO];
+    if let Some(extensions) = file_extensions {
+      let _: () = msg_send![dialog, setCanChooseDirectories: NO];
+      let _: () = msg_send![dialog, setCanChooseFiles: YES];
+      // NSOpenPanel only looks at the last extension of a file name, so e.g.
+      // `tar.gz` has to be turned into `gz`
+      let file_types: Vec<id> = extensions
+        .iter()
+        .map(|extension| {
+          let extension = extension.rsplit('.').next().unwrap();
+          NSString::alloc(nil).init_str(extension).autorelease()
+        })
+        .collect();
+      let file_types: id = NSArray::arrayWithObjects(nil, &file_types);
+      let _: () = msg_send![dialog, setAllowedFileTypes: file_types];
+    } else {
+      let _: () = msg_send![dialog, setCanChooseDirectories: YES];
+      let _: () = msg_send![dialog, setCanCreateDirectories: YES];
+      let _: () = msg_send![dialog, setCanChooseFiles: NO];
+    }
     let response: NSModalResponse = msg_send![dialog, runModal];
 
     if response == NSModalResponseOK {
@@ -135,6 +165,125 @@ pub fn open_pick_folder_dialog() -> Option<PathBuf> {
   })
 }
 
+// the values of NSButtonTypeSwitch (i.e. a checkbox)
+const NS_BUTTON_TYPE_SWITCH: NSUInteger = 3;
+
+// The list is put into the accessory view of an alert: a pop-up button when a
+// single item has to be chosen and a column of checkboxes otherwise.
+pub fn show_choice_dialog(
+  title: &str,
+  items: &[String],
+  multiple: bool,
+  checked: &[bool],
+) -> Option<Vec<bool>> {
+  const WIDTH: f64 = 460.0;
+  const COLUMN_WIDTH: f64 = 240.0;
+  // long lists (e.g. of mods) are split into columns to fit on the screen
+  const MAX_ROWS: usize = 16;
+  const ITEM_HEIGHT: f64 = 22.0;
+
+  request_focus();
+
+  autorelease(|| unsafe {
+    let alert: id = msg_send![class!(NSAlert), alloc];
+    let _: () = msg_send![alert, init];
+    let _: () = msg_send![alert, autorelease];
+
+    let ns_string: id = NSString::alloc(nil).init_str(title);
+    let _: () = msg_send![alert, setMessageText: ns_string];
+    for button_text in &["OK", "Cancel"] {
+      let ns_string: id = NSString::alloc(nil).init_str(button_text);
+      let _: () = msg_send![alert, addButtonWithTitle: ns_string];
+    }
+
+    let accessory_view: id;
+    let mut buttons: Vec<id> = Vec::with_capacity(items.len());
+    if multiple {
+      let columns = (items.len() + MAX_ROWS - 1) / MAX_ROWS;
+      let rows = if columns > 1 {
+        (items.len() + columns - 1) / columns
+      } else {
+        items.len()
+      };
+      let column_width = if columns > 1 { COLUMN_WIDTH } else { WIDTH };
+      let height = ITEM_HEIGHT * rows as f64;
+      let view: id = msg_send![class!(NSView), alloc];
+      accessory_view = msg_send![
+        view,
+        initWithFrame: NSRect::new(
+          NSPoint::new(0.0, 0.0),
+          NSSize::new(WIDTH.max(column_width * columns as f64), height),
+        )
+      ];
+      let _: () = msg_send![accessory_view, autorelease];
+
+      for (index, (item, &is_checked)) in items.iter().zip(checked).enumerate()
+      {
+        let button: id = msg_send![class!(NSButton), alloc];
+        // the origin of the coordinate system is in the bottom left corner
+        let button: id = msg_send![
+          button,
+          initWithFrame: NSRect::new(
+            NSPoint::new(
+              column_width * (index / rows) as f64,
+              height - ITEM_HEIGHT * (index % rows + 1) as f64,
+            ),
+            NSSize::new(column_width, ITEM_HEIGHT),
+          )
+        ];
+        let button_type = NS_BUTTON_TYPE_SWITCH;
+        let _: () = msg_send![button, setButtonType: button_type];
+        let ns_string: id = NSString::alloc(nil).init_str(item);
+        let _: () = msg_send![button, setTitle: ns_string];
+        let state: NSInteger = if is_checked { 1 } else { 0 };
+        let _: () = msg_send![button, setState: state];
+        let _: () = msg_send![accessory_view, addSubview: button];
+        let _: () = msg_send![button, release];
+        buttons.push(button);
+      }
+    } else {
+      let pop_up_button: id = msg_send![class!(NSPopUpButton), alloc];
+      accessory_view = msg_send![
+        pop_up_button,
+        initWithFrame: NSRect::new(
+          NSPoint::new(0.0, 0.0),
+          NSSize::new(WIDTH, 26.0),
+        )
+        pullsDown: NO
+      ];
+      let _: () = msg_send![accessory_view, autorelease];
+      for item in items {
+        let ns_string: id = NSString::alloc(nil).init_str(item);
+        let _: () = msg_send![accessory_view, addItemWithTitle: ns_string];
+      }
+      if let Some(index) = checked.iter().position(|&c| c) {
+        let index = index as NSInteger;
+        let _: () = msg_send![accessory_view, selectItemAtIndex: index];
+      }
+    }
+    let _: () = msg_send![alert, setAccessoryView: accessory_view];
+
+    let response: NSModalResponse = msg_send![alert, runModal];
+    if response != NSAlertFirstButtonReturn {
+      return None;
+    }
+
+    Some(if multiple {
+      buttons
+        .iter()
+        .map(|&button| {
+          let state: NSInteger = msg_send![button, state];
+          state != 0
+        })
+        .collect()
+    } else {
+      let selected_index: NSInteger =
+        msg_send![accessory_view, indexOfSelectedItem];
+      (0..items.len()).map(|i| i as NSInteger == selected_index).collect()
+    })
+  })
+}
+
 pub fn open_path(path: &Path) {
   autorelease(|| unsafe {
     let shared_workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
@@ -148,3 +297,168 @@ pub fn open_path(path: &Path) {
     let _: () = msg_send![shared_workspace, openURL: ns_url];
   })
 }
+
+// the values of NSWindowStyleMaskTitled and NSBackingStoreBuffered
+const NS_WINDOW_STYLE_MASK_TITLED: NSUInteger = 1;
+const NS_BACKING_STORE_BUFFERED: NSUInteger = 2;
+// the values of NSButtonTypePushOnPushOff and NSBezelStyleRounded
+const NS_BUTTON_TYPE_PUSH_ON_PUSH_OFF: NSUInteger = 1;
+const NS_BEZEL_STYLE_ROUNDED: NSUInteger = 1;
+
+pub struct ProgressWindow {
+  window: id,
+  label: id,
+  progress_indicator: id,
+  cancel_button: id,
+}
+
+impl ProgressWindow {
+  pub fn new() -> Self {
+    request_focus();
+
+    let this = autorelease(|| unsafe {
+      let window: id = msg_send![class!(NSWindow), alloc];
+      // the window can't be closed by the user, so that the operation is
+      // always stopped with the cancel button
+      let window: id = msg_send![
+        window,
+        initWithContentRect: NSRect::new(
+          NSPoint::new(0.0, 0.0),
+          NSSize::new(400.0, 110.0),
+        )
+        styleMask: NS_WINDOW_STYLE_MASK_TITLED
+        backing: NS_BACKING_STORE_BUFFERED
+        defer: NO
+      ];
+      let _: () = msg_send![window, setReleasedWhenClosed: NO];
+      let title: id = NSString::alloc(nil).init_str(crate::PKG_NAME);
+      let _: () = msg_send![window, setTitle: title];
+      let content_view: id = msg_send![window, contentView];
+
+      // the origin of the coordinate system is in the bottom left corner
+      let empty_string: id = NSString::alloc(nil).init_str("");
+      let label: id =
+        msg_send![class!(NSTextField), labelWithString: empty_string];
+      let _: () = msg_send![label, setFrame: NSRect::new(
+        NSPoint::new(20.0, 78.0),
+        NSSize::new(360.0, 20.0),
+      )];
+      let _: () = msg_send![content_view, addSubview: label];
+
+      let progress_indicator: id =
+        msg_send![class!(NSProgressIndicator), alloc];
+      let progress_indicator: id = msg_send![
+        progress_indicator,
+        initWithFrame: NSRect::new(
+          NSPoint::new(20.0, 50.0),
+          NSSize::new(360.0, 20.0),
+        )
+      ];
+      let _: () = msg_send![progress_indicator, setIndeterminate: NO];
+      let _: () = msg_send![progress_indicator, setMinValue: 0.0f64];
+      let _: () = msg_send![progress_indicator, setMaxValue: 1.0f64];
+      let _: () = msg_send![content_view, addSubview: progress_indicator];
+      let _: () = msg_send![progress_indicator, release];
+
+      // implementing a target for the button's action requires declaring an
+      // Objective-C class, instead the button is made a toggle and its state
+      // is polled
+      let cancel_button: id = msg_send![class!(NSButton), alloc];
+      let cancel_button: id = msg_send![
+        cancel_button,
+        initWithFrame: NSRect::new(
+          NSPoint::new(294.0, 12.0),
+          NSSize::new(92.0, 32.0),
+        )
+      ];
+      let button_type = NS_BUTTON_TYPE_PUSH_ON_PUSH_OFF;
+      let _: () = msg_send![cancel_button, setButtonType: button_type];
+      let bezel_style = NS_BEZEL_STYLE_ROUNDED;
+      let _: () = msg_send![cancel_button, setBezelStyle: bezel_style];
+      let button_title: id = NSString::alloc(nil).init_str("Cancel");
+      let _: () = msg_send![cancel_button, setTitle: button_title];
+      // pressing Escape is the same as clicking the button
+      let key_equivalent: id = NSString::alloc(nil).init_str("\u{1b}");
+      let _: () = msg_send![cancel_button, setKeyEquivalent: key_equivalent];
+      let _: () = msg_send![content_view, addSubview: cancel_button];
+      let _: () = msg_send![cancel_button, release];
+
+      let _: () = msg_send![window, center];
+      let _: () = msg_send![window, makeKeyAndOrderFront: nil];
+
+      Self { window, label, progress_indicator, cancel_button }
+    });
+
+    this.process_events();
+    this
+  }
+
+  pub fn set_text(&self, text: &str) {
+    autorelease(|| unsafe {
+      let ns_string: id = NSString::alloc(nil).init_str(text);
+      let _: () = msg_send![self.label, setStringValue: ns_string];
+    });
+    self.process_events();
+  }
+
+  pub fn set_fraction(&self, fraction: Option<f64>) {
+    unsafe {
+      let progress_indicator = self.progress_indicator;
+      match fraction {
+        Some(fraction) => {
+          let _: () = msg_send![progress_indicator, setIndeterminate: NO];
+          let value: f64 = fraction.clamp(0.0, 1.0);
+          let _: () = msg_send![progress_indicator, setDoubleValue: value];
+        }
+        None => {
+          let _: () = msg_send![progress_indicator, setIndeterminate: YES];
+          let _: () = msg_send![progress_indicator, startAnimation: nil];
+        }
+      }
+    }
+    self.process_events();
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.process_events();
+    unsafe {
+      let state: NSInteger = msg_send![self.cancel_button, state];
+      let cancelled = state != 0;
+      if cancelled {
+        let _: () = msg_send![self.cancel_button, setEnabled: NO];
+      }
+      cancelled
+    }
+  }
+
+  fn process_events(&self) {
+    autorelease(|| unsafe {
+      let app: id = NSApp();
+      let distant_past: id = msg_send![class!(NSDate), distantPast];
+      loop {
+        let event: id = msg_send![
+          app,
+          nextEventMatchingMask: NSUInteger::max_value()
+          untilDate: distant_past
+          inMode: NSDefaultRunLoopMode
+          dequeue: YES
+        ];
+        if event == nil {
+          break;
+        }
+        let _: () = msg_send![app, sendEvent: event];
+      }
+      let _: () = msg_send![app, updateWindows];
+    })
+  }
+}
+
+impl Drop for ProgressWindow {
+  fn drop(&mut self) {
+    unsafe {
+      let _: () = msg_send![self.window, close];
+      let _: () = msg_send![self.window, release];
+    }
+    self.process_events();
+  }
+}
@@ -76,6 +76,64 @@ pub fn open_pick_folder_dialog() -> Option<PathBuf> {
   sys::open_pick_folder_dialog()
 }
 
+// `extensions` are given without the leading dot, e.g. `tar.gz`
+pub fn open_pick_file_dialog(
+  filter_name: &str,
+  extensions: &[&str],
+) -> Option<PathBuf> {
+  sys::open_pick_file_dialog(filter_name, extensions)
+}
+
+// Shows a modal dialog with a list of items of which exactly one has to be
+// picked, the first one is selected initially. Returns the index of the chosen
+// item or `None` if the dialog was cancelled.
+pub fn show_list_selection_dialog(
+  title: &str,
+  items: &[String],
+) -> Option<usize> {
+  let mut checked = vec![false; items.len()];
+  if let Some(first) = checked.first_mut() {
+    *first = true;
+  }
+  let checked = sys::show_choice_dialog(title, items, false, &checked)?;
+  checked.iter().position(|&c| c)
+}
+
+// Shows a modal dialog with a check box for every item. Returns the new states
+// of the check boxes or `None` if the dialog was cancelled.
+pub fn show_checklist_dialog(
+  title: &str,
+  items: &[String],
+  checked: &[bool],
+) -> Option<Vec<bool>> {
+  sys::show_choice_dialog(title, items, true, checked)
+}
+
 pub fn open_path(path: &Path) {
   sys::open_path(path)
 }
+
+// A non-modal window with a status text, a progress bar and a cancel button.
+// Work is done on the main thread, so pending UI events are processed whenever
+// one of the methods is called, which must happen regularly to keep the window
+// responsive. The window is closed when dropped.
+pub struct ProgressWindow(sys::ProgressWindow);
+
+impl ProgressWindow {
+  pub fn new() -> Self {
+    Self(sys::ProgressWindow::new())
+  }
+
+  pub fn set_text(&self, text: &str) {
+    self.0.set_text(text)
+  }
+
+  // `None` switches the progress bar to the indeterminate mode
+  pub fn set_fraction(&self, fraction: Option<f64>) {
+    self.0.set_fraction(fraction)
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.is_cancelled()
+  }
+}
@@ -1,21 +1,33 @@
 #![allow(non_upper_case_globals)]
 
+use std::cell::Cell;
 use std::ffi::OsString;
+use std::mem;
+use std::os::raw::c_int;
 use std::path::{Path, PathBuf};
 use std::ptr::{null, null_mut};
 
-use winapi::shared::minwindef::{HINSTANCE, INT, LPVOID, UINT};
+use winapi::shared::basetsd::LONG_PTR;
+use winapi::shared::minwindef::{
+  DWORD, FALSE, HINSTANCE, INT, LOWORD, LPARAM, LPVOID, LRESULT, TRUE, UINT,
+  WPARAM,
+};
 use winapi::shared::ntdef::{HRESULT, LPWSTR};
+use winapi::shared::windef::{HBRUSH, HMENU, HWND, RECT};
 use winapi::shared::winerror::{
   ERROR_CANCELLED, HRESULT_FROM_WIN32, SUCCEEDED, S_FALSE, S_OK,
 };
 use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
 use winapi::um::combaseapi::*;
+use winapi::um::commctrl::*;
 use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::libloaderapi::GetModuleHandleW;
 use winapi::um::objbase::{COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE};
 use winapi::um::shellapi::ShellExecuteW;
 use winapi::um::shobjidl::*;
 use winapi::um::shobjidl_core::*;
+use winapi::um::shtypes::COMDLG_FILTERSPEC;
+use winapi::um::wingdi::{GetStockObject, SelectObject, DEFAULT_GUI_FONT};
 use winapi::um::winuser::*;
 use winapi::{Interface, DEFINE_GUID};
 use wio::com::ComPtr;
@@ -97,6 +109,20 @@ pub fn show_alert(config: AlertConfig) -> Option<AlertResponse> {
 }
 
 pub fn open_pick_folder_dialog() -> Option<PathBuf> {
+  run_file_open_dialog(FOS_PICKFOLDERS, None)
+}
+
+pub fn open_pick_file_dialog(
+  filter_name: &str,
+  extensions: &[&str],
+) -> Option<PathBuf> {
+  run_file_open_dialog(FOS_FILEMUSTEXIST, Some((filter_name, extensions)))
+}
+
+fn run_file_open_dialog(
+  options: FILEOPENDIALOGOPTIONS,
+  filter: Option<(&str, &[&str])>,
+) -> Option<PathBuf> {
   unsafe {
     // taken from https://github.com/xi-editor/druid/blob/bafa1b9cb0fe156e9800a17f5d5e751be4ef6286/druid-shell/src/platform/windows/dialog.rs#L74-L155
 
@@ -110,7 +136,21 @@ pub fn open_pick_folder_dialog() -> Option<PathBuf> {
     ));
     let file_dialog = ComPtr::from_raw(file_dialog);
 
-    check_hresult(file_dialog.SetOptions(FOS_PICKFOLDERS));
+    check_hresult(file_dialog.SetOptions(options));
+
+    if let Some((filter_name, extensions)) = filter {
+      let name: Vec<u16> = filter_name.to_wide_null();
+      let spec: Vec<u16> = extensions
+        .iter()
+        .map(|extension| format!("*.{}", extension))
+        .collect::<Vec<String>>()
+        .join(";")
+        .to_wide_null();
+      let filter_spec =
+        COMDLG_FILTERSPEC { pszName: name.as_ptr(), pszSpec: spec.as_ptr() };
+      // the dialog copies the strings
+      check_hresult(file_dialog.SetFileTypes(1, &filter_spec));
+    }
 
     let hr = file_dialog.Show(null_mut());
     if hr == HRESULT_FROM_WIN32(ERROR_CANCELLED) {
@@ -160,3 +200,458 @@ pub fn open_path(path: &Path) {
     panic!("ShellExecuteW error (HINSTANCE): {:?}", result);
   }
 }
+
+// Registers the window class on the first use and creates a top-level window
+// with the given client area size in the center of the screen.
+unsafe fn create_centered_window(
+  instance: HINSTANCE,
+  class_name: &str,
+  window_proc: unsafe extern "system" fn(HWND, UINT, WPARAM, LPARAM) -> LRESULT,
+  client_width: c_int,
+  client_height: c_int,
+) -> HWND {
+  let class_name: Vec<u16> = class_name.to_wide_null();
+  let window_class = WNDCLASSW {
+    style: 0,
+    lpfnWndProc: Some(window_proc),
+    cbClsExtra: 0,
+    cbWndExtra: 0,
+    hInstance: instance,
+    hIcon: null_mut(),
+    hCursor: LoadCursorW(null_mut(), IDC_ARROW),
+    hbrBackground: (COLOR_BTNFACE + 1) as HBRUSH,
+    lpszMenuName: null(),
+    lpszClassName: class_name.as_ptr(),
+  };
+  // fails when the class has already been registered by a previously created
+  // window, which is fine
+  RegisterClassW(&window_class);
+
+  let style: DWORD = WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU;
+  let mut rect =
+    RECT { left: 0, top: 0, right: client_width, bottom: client_height };
+  AdjustWindowRect(&mut rect, style, FALSE);
+  let width = rect.right - rect.left;
+  let height = rect.bottom - rect.top;
+
+  let title: Vec<u16> = crate::PKG_NAME.to_wide_null();
+  let window: HWND = CreateWindowExW(
+    0,
+    class_name.as_ptr(),
+    title.as_ptr(),
+    style,
+    (GetSystemMetrics(SM_CXSCREEN) - width) / 2,
+    (GetSystemMetrics(SM_CYSCREEN) - height) / 2,
+    width,
+    height,
+    null_mut(),
+    null_mut(),
+    instance,
+    null_mut(),
+  );
+  if window.is_null() {
+    panic!("CreateWindowExW error (DWORD): {:?}", GetLastError());
+  }
+  window
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn create_control(
+  instance: HINSTANCE,
+  parent: HWND,
+  class: &str,
+  text: &str,
+  style: DWORD,
+  x: c_int,
+  y: c_int,
+  width: c_int,
+  height: c_int,
+  id: c_int,
+) -> HWND {
+  let class: Vec<u16> = class.to_wide_null();
+  let text: Vec<u16> = text.to_wide_null();
+  let control: HWND = CreateWindowExW(
+    0,
+    class.as_ptr(),
+    text.as_ptr(),
+    WS_CHILD | WS_VISIBLE | style,
+    x,
+    y,
+    width,
+    height,
+    parent,
+    id as HMENU,
+    instance,
+    null_mut(),
+  );
+  // the default font of controls is the bold bitmap font from the Windows 3.x
+  // days
+  let font = GetStockObject(DEFAULT_GUI_FONT as c_int);
+  SendMessageW(control, WM_SETFONT, font as WPARAM, TRUE as LPARAM);
+  control
+}
+
+// Computes the height of a label created with `create_control` when its text
+// is wrapped to the given width.
+unsafe fn measure_label_height(text: &str, width: c_int) -> c_int {
+  let text: Vec<u16> = text.to_wide_null();
+  let dc = GetDC(null_mut());
+  let previous_font =
+    SelectObject(dc, GetStockObject(DEFAULT_GUI_FONT as c_int));
+  let mut rect = RECT { left: 0, top: 0, right: width, bottom: 0 };
+  DrawTextW(
+    dc,
+    text.as_ptr(),
+    -1,
+    &mut rect,
+    DT_CALCRECT | DT_WORDBREAK | DT_EXPANDTABS,
+  );
+  SelectObject(dc, previous_font);
+  ReleaseDC(null_mut(), dc);
+  rect.bottom - rect.top
+}
+
+const PROGRESS_WINDOW_CLASS_NAME: &str = "ccloader-installer-progress";
+// the progress bar works with integers, so fractions are mapped to this range
+const PROGRESS_BAR_MAX: WPARAM = 1000;
+
+pub struct ProgressWindow {
+  window: HWND,
+  label: HWND,
+  progress_bar: HWND,
+  // boxed so that its address, which is stored in the window's user data,
+  // stays the same when the struct is moved
+  cancelled: Box<Cell<bool>>,
+}
+
+impl ProgressWindow {
+  pub fn new() -> Self {
+    unsafe {
+      let instance: HINSTANCE = GetModuleHandleW(null());
+
+      let init_controls = INITCOMMONCONTROLSEX {
+        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as DWORD,
+        dwICC: ICC_PROGRESS_CLASS,
+      };
+      InitCommonControlsEx(&init_controls);
+
+      let window = create_centered_window(
+        instance,
+        PROGRESS_WINDOW_CLASS_NAME,
+        progress_window_proc,
+        400,
+        110,
+      );
+      let create_control = |class: &str, text: &str, style, x, y, w, h, id| {
+        create_control(instance, window, class, text, style, x, y, w, h, id)
+      };
+
+      let label = create_control(
+        "STATIC",
+        "",
+        SS_LEFT | SS_ENDELLIPSIS,
+        12,
+        12,
+        376,
+        20,
+        0,
+      );
+      let progress_bar =
+        create_control(PROGRESS_CLASS, "", 0, 12, 40, 376, 20, 0);
+      SendMessageW(progress_bar, PBM_SETRANGE32, 0, PROGRESS_BAR_MAX as LPARAM);
+      create_control(
+        "BUTTON",
+        "Cancel",
+        WS_TABSTOP | BS_PUSHBUTTON,
+        301,
+        74,
+        87,
+        24,
+        IDCANCEL,
+      );
+
+      let cancelled = Box::new(Cell::new(false));
+      SetWindowLongPtrW(
+        window,
+        GWLP_USERDATA,
+        &*cancelled as *const Cell<bool> as LONG_PTR,
+      );
+
+      ShowWindow(window, SW_SHOW);
+      UpdateWindow(window);
+
+      let this = Self { window, label, progress_bar, cancelled };
+      this.process_events();
+      this
+    }
+  }
+
+  pub fn set_text(&self, text: &str) {
+    let text: Vec<u16> = text.to_wide_null();
+    unsafe {
+      SetWindowTextW(self.label, text.as_ptr());
+    }
+    self.process_events();
+  }
+
+  pub fn set_fraction(&self, fraction: Option<f64>) {
+    // the marquee style for indeterminate progress requires version 6 of the
+    // common controls library, so the bar is just left as is in that case
+    if let Some(fraction) = fraction {
+      let position =
+        (fraction.clamp(0.0, 1.0) * PROGRESS_BAR_MAX as f64) as WPARAM;
+      unsafe {
+        SendMessageW(self.progress_bar, PBM_SETPOS, position, 0);
+      }
+    }
+    self.process_events();
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.process_events();
+    self.cancelled.get()
+  }
+
+  fn process_events(&self) {
+    unsafe {
+      let mut msg: MSG = mem::zeroed();
+      while PeekMessageW(&mut msg, null_mut(), 0, 0, PM_REMOVE) != FALSE {
+        // handles keyboard navigation, e.g. pressing Escape or Enter
+        if IsDialogMessageW(self.window, &mut msg) == FALSE {
+          TranslateMessage(&msg);
+          DispatchMessageW(&msg);
+        }
+      }
+    }
+  }
+}
+
+impl Drop for ProgressWindow {
+  fn drop(&mut self) {
+    unsafe {
+      DestroyWindow(self.window);
+    }
+    self.process_events();
+  }
+}
+
+unsafe extern "system" fn progress_window_proc(
+  window: HWND,
+  msg: UINT,
+  wparam: WPARAM,
+  lparam: LPARAM,
+) -> LRESULT {
+  let set_cancelled = || {
+    let cancelled =
+      GetWindowLongPtrW(window, GWLP_USERDATA) as *const Cell<bool>;
+    if !cancelled.is_null() {
+      (*cancelled).set(true);
+    }
+  };
+
+  match msg {
+    WM_COMMAND if LOWORD(wparam as DWORD) as c_int == IDCANCEL => {
+      set_cancelled();
+      // `lparam` contains the handle of the button, it is NULL when the
+      // command comes from the Escape key
+      if lparam != 0 {
+        EnableWindow(lparam as HWND, FALSE);
+      }
+      0
+    }
+    // closing the window is treated just like pressing the cancel button, but
+    // the window itself stays until the operation is actually stopped
+    WM_CLOSE => {
+      set_cancelled();
+      0
+    }
+    _ => DefWindowProcW(window, msg, wparam, lparam),
+  }
+}
+
+const CHOICE_WINDOW_CLASS_NAME: &str = "ccloader-installer-choice";
+// the ID of the first item, the following items get consecutive IDs
+const CHOICE_ITEM_FIRST_ID: c_int = 100;
+
+// There is no standard dialog with a list of radio buttons or checkboxes (task
+// dialogs support only radio buttons and require version 6 of the common
+// controls library), so a window is put together by hand and run modally.
+pub fn show_choice_dialog(
+  title: &str,
+  items: &[String],
+  multiple: bool,
+  checked: &[bool],
+) -> Option<Vec<bool>> {
+  const MIN_WIDTH: c_int = 480;
+  const COLUMN_WIDTH: c_int = 240;
+  // long lists (e.g. of mods) are split into columns to fit on the screen
+  const MAX_ROWS: usize = 16;
+  const ITEM_HEIGHT: c_int = 22;
+  const MIN_LABEL_HEIGHT: c_int = 16;
+  const BUTTON_WIDTH: c_int = 87;
+  const BUTTON_HEIGHT: c_int = 24;
+
+  let columns = (items.len() + MAX_ROWS - 1) / MAX_ROWS;
+  let rows = if columns > 1 {
+    (items.len() + columns - 1) / columns
+  } else {
+    items.len()
+  };
+  let column_width = if columns > 1 { COLUMN_WIDTH } else { MIN_WIDTH - 24 };
+  let width = MIN_WIDTH.max(columns as c_int * COLUMN_WIDTH + 24);
+
+  unsafe {
+    let instance: HINSTANCE = GetModuleHandleW(null());
+
+    // prompts such as the ones with the path to the game data directory take
+    // several lines
+    let label_height =
+      measure_label_height(title, width - 24).max(MIN_LABEL_HEIGHT);
+    let items_top = 12 + label_height + 8;
+    let buttons_top = items_top + rows as c_int * ITEM_HEIGHT + 12;
+    let height = buttons_top + BUTTON_HEIGHT + 12;
+    let window = create_centered_window(
+      instance,
+      CHOICE_WINDOW_CLASS_NAME,
+      choice_window_proc,
+      width,
+      height,
+    );
+    let create_control = |class: &str, text: &str, style, x, y, w, h, id| {
+      create_control(instance, window, class, text, style, x, y, w, h, id)
+    };
+
+    create_control(
+      "STATIC",
+      title,
+      SS_LEFT,
+      12,
+      12,
+      width - 24,
+      label_height,
+      0,
+    );
+
+    let mut buttons: Vec<HWND> = Vec::with_capacity(items.len());
+    for (index, (item, &is_checked)) in items.iter().zip(checked).enumerate() {
+      let style: DWORD = WS_TABSTOP
+        | if multiple {
+          BS_AUTOCHECKBOX
+        } else if index == 0 {
+          // starts the group of radio buttons, the group ends at the next
+          // control with WS_GROUP
+          BS_AUTORADIOBUTTON | WS_GROUP
+        } else {
+          BS_AUTORADIOBUTTON
+        };
+      let button = create_control(
+        "BUTTON",
+        item,
+        style,
+        12 + (index / rows) as c_int * column_width,
+        items_top + (index % rows) as c_int * ITEM_HEIGHT,
+        column_width,
+        ITEM_HEIGHT,
+        CHOICE_ITEM_FIRST_ID + index as c_int,
+      );
+      if is_checked {
+        SendMessageW(button, BM_SETCHECK, BST_CHECKED, 0);
+      }
+      buttons.push(button);
+    }
+
+    create_control(
+      "BUTTON",
+      "OK",
+      WS_TABSTOP | WS_GROUP | BS_DEFPUSHBUTTON,
+      width - 12 - BUTTON_WIDTH * 2 - 8,
+      buttons_top,
+      BUTTON_WIDTH,
+      BUTTON_HEIGHT,
+      IDOK,
+    );
+    create_control(
+      "BUTTON",
+      "Cancel",
+      WS_TABSTOP | BS_PUSHBUTTON,
+      width - 12 - BUTTON_WIDTH,
+      buttons_top,
+      BUTTON_WIDTH,
+      BUTTON_HEIGHT,
+      IDCANCEL,
+    );
+
+    // `None` while the dialog is open, then whether OK has been pressed
+    let response: Box<Cell<Option<bool>>> = Box::new(Cell::new(None));
+    SetWindowLongPtrW(
+      window,
+      GWLP_USERDATA,
+      &*response as *const Cell<Option<bool>> as LONG_PTR,
+    );
+
+    ShowWindow(window, SW_SHOW);
+    SetForegroundWindow(window);
+    if let Some(&first_button) = buttons.first() {
+      SetFocus(first_button);
+    }
+
+    let mut msg: MSG = mem::zeroed();
+    while response.get().is_none()
+      && GetMessageW(&mut msg, null_mut(), 0, 0) > 0
+    {
+      // handles keyboard navigation, e.g. pressing Escape, Enter or arrows
+      if IsDialogMessageW(window, &mut msg) == FALSE {
+        TranslateMessage(&msg);
+        DispatchMessageW(&msg);
+      }
+    }
+
+    let result = if response.get() == Some(true) {
+      Some(
+        buttons
+          .iter()
+          .map(|&button| {
+            SendMessageW(button, BM_GETCHECK, 0, 0) == BST_CHECKED as LRESULT
+          })
+          .collect(),
+      )
+    } else {
+      None
+    };
+
+    DestroyWindow(window);
+
+    result
+  }
+}
+
+unsafe extern "system" fn choice_window_proc(
+  window: HWND,
+  msg: UINT,
+  wparam: WPARAM,
+  lparam: LPARAM,
+) -> LRESULT {
+  let set_response = |ok: bool| {
+    let response =
+      GetWindowLongPtrW(window, GWLP_USERDATA) as *const Cell<Option<bool>>;
+    if !response.is_null() {
+      (*response).set(Some(ok));
+    }
+  };
+
+  match msg {
+    WM_COMMAND if LOWORD(wparam as DWORD) as c_int == IDOK => {
+      set_response(true);
+      0
+    }
+    WM_COMMAND if LOWORD(wparam as DWORD) as c_int == IDCANCEL => {
+      set_response(false);
+      0
+    }
+    WM_CLOSE => {
+      set_response(false);
+      0
+    }
+    _ => DefWindowProcW(window, msg, wparam, lparam),
+  }
+}
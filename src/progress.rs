@@ -0,0 +1,96 @@
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use log::info;
+
+use crate::native_ui::ProgressWindow;
+
+// Something that displays the progress of a long-running operation to the
+// user and possibly lets them cancel it.
+pub trait Progress {
+  fn set_status(&self, text: &str);
+  // `None` means that the progress can't be measured at the moment
+  fn set_fraction(&self, fraction: Option<f64>);
+  fn is_cancelled(&self) -> bool;
+}
+
+impl Progress for ProgressWindow {
+  fn set_status(&self, text: &str) {
+    self.set_text(text)
+  }
+
+  fn set_fraction(&self, fraction: Option<f64>) {
+    self.set_fraction(fraction)
+  }
+
+  fn is_cancelled(&self) -> bool {
+    self.is_cancelled()
+  }
+}
+
+// Used in the headless mode, where the progress is simply written to the log.
+// Only every 10% are logged so that the output is not flooded.
+#[derive(Debug)]
+pub struct LogProgress {
+  last_logged_percent: Cell<Option<u32>>,
+}
+
+impl LogProgress {
+  pub fn new() -> Self {
+    Self { last_logged_percent: Cell::new(None) }
+  }
+}
+
+impl Progress for LogProgress {
+  fn set_status(&self, text: &str) {
+    info!("{}", text);
+    self.last_logged_percent.set(None);
+  }
+
+  fn set_fraction(&self, fraction: Option<f64>) {
+    if let Some(fraction) = fraction {
+      let percent = (fraction.clamp(0.0, 1.0) * 100.0) as u32 / 10 * 10;
+      if self.last_logged_percent.get().is_none_or(|p| percent > p) {
+        info!("{}%", percent);
+        self.last_logged_percent.set(Some(percent));
+      }
+    }
+  }
+
+  fn is_cancelled(&self) -> bool {
+    false
+  }
+}
+
+// Reports the number of bytes read out of `total` and fails with an error once
+// the operation gets cancelled.
+pub struct ProgressReader<R> {
+  inner: R,
+  progress: Rc<dyn Progress>,
+  bytes_read: u64,
+  total: u64,
+}
+
+impl<R: Read> ProgressReader<R> {
+  pub fn new(inner: R, total: u64, progress: Rc<dyn Progress>) -> Self {
+    Self { inner, progress, bytes_read: 0, total }
+  }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self.progress.is_cancelled() {
+      return Err(io::Error::other("cancelled by the user"));
+    }
+
+    let len = self.inner.read(buf)?;
+    self.bytes_read += len as u64;
+    if self.total > 0 {
+      self
+        .progress
+        .set_fraction(Some(self.bytes_read as f64 / self.total as f64));
+    }
+    Ok(len)
+  }
+}
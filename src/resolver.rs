@@ -0,0 +1,700 @@
+// Resolution of the dependencies between CCModDB packages. CCModDB lists only
+// the latest version of every package, so there are at most two candidates
+// for each mod: the copy which is already installed and the one from the
+// database. The installed copy is kept as long as it satisfies every
+// requirement, otherwise the mod gets updated.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use log::{info, warn};
+use semver::{Version, VersionReq};
+
+use crate::ccmoddb::{self, Database, Package};
+use crate::error::Result as AppResult;
+use crate::game;
+use crate::installer::{self, MODS_DIR_PATH};
+use crate::mods::{self, InstalledMod};
+use crate::progress::Progress;
+
+// the names which stand for the game and for the runtime in dependency lists
+const GAME_PACKAGE_NAME: &str = "crosscode";
+const NWJS_PACKAGE_NAME: &str = "nwjs";
+
+// Everything the requirements are checked against.
+#[derive(Debug)]
+pub struct Environment {
+  pub game_version: Option<String>,
+  pub nwjs_version: Option<String>,
+  pub ccloader_version: Option<String>,
+  pub installed_mods: Vec<InstalledMod>,
+}
+
+impl Environment {
+  pub fn detect(
+    game_data_dir: &Path,
+    progress: Rc<dyn Progress>,
+  ) -> AppResult<Self> {
+    let ccloader_version =
+      if game_data_dir.join(*installer::CCLOADER_DIR_PATH).is_dir() {
+        installer::detect_installed_ccloader_version(game_data_dir)
+      } else {
+        None
+      };
+    Ok(Self {
+      game_version: game::detect_game_version(game_data_dir),
+      nwjs_version: game::detect_nwjs_version(game_data_dir, progress),
+      ccloader_version,
+      installed_mods: mods::installed_mods(game_data_dir)?,
+    })
+  }
+
+  fn installed_mod(&self, name: &str) -> Option<&InstalledMod> {
+    self.installed_mods.iter().find(|m| m.name == name)
+  }
+}
+
+// A mod which has to be installed or updated.
+#[derive(Debug)]
+pub struct Step {
+  pub package: Package,
+  // relative to the game data directory
+  pub rel_path: PathBuf,
+  // `None` for mods which aren't installed yet
+  pub installed_version: Option<String>,
+  pub required_by: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Selection<'a> {
+  Installed(&'a InstalledMod),
+  // the version from the database
+  Planned(String),
+  // missing from the database, this has already been reported
+  Unavailable,
+}
+
+#[derive(Debug)]
+struct Requirement {
+  name: String,
+  req: String,
+  required_by: String,
+}
+
+struct Resolver<'a> {
+  database: &'a Database,
+  environment: &'a Environment,
+  selected: HashMap<String, Selection<'a>>,
+  requirements: Vec<Requirement>,
+  // in the order of installation, i.e. dependencies come first
+  steps: Vec<Step>,
+  problems: Vec<String>,
+}
+
+// Returns the mods which have to be installed or updated, in dependency order,
+// to get the requested mods working. Unmet requirements on the game, nw.js or
+// CCLoader are only logged when `ignore_compatibility` is set.
+pub fn resolve(
+  database: &Database,
+  environment: &Environment,
+  requested: &[String],
+  ignore_compatibility: bool,
+) -> AppResult<Vec<Step>> {
+  resolve_impl(database, environment, requested, &[], ignore_compatibility)
+}
+
+// Like `resolve`, but the given installed mods are always updated to the
+// versions from the database.
+pub fn resolve_updates(
+  database: &Database,
+  environment: &Environment,
+  updated: &[String],
+  ignore_compatibility: bool,
+) -> AppResult<Vec<Step>> {
+  resolve_impl(database, environment, &[], updated, ignore_compatibility)
+}
+
+fn resolve_impl(
+  database: &Database,
+  environment: &Environment,
+  requested: &[String],
+  updated: &[String],
+  ignore_compatibility: bool,
+) -> AppResult<Vec<Step>> {
+  let mut resolver = Resolver {
+    database,
+    environment,
+    selected: HashMap::new(),
+    requirements: Vec::new(),
+    steps: Vec::new(),
+    problems: Vec::new(),
+  };
+
+  for name in requested {
+    if let Some(installed) = environment.installed_mod(name) {
+      info!(
+        "{} v{} is already installed",
+        name,
+        installed.version.as_deref().unwrap_or("<unknown>")
+      );
+    }
+    resolver.visit(name, None);
+  }
+  for name in updated {
+    if !resolver.selected.contains_key(name.as_str()) {
+      resolver.plan(name, environment.installed_mod(name), None);
+    }
+  }
+
+  let mut compatibility_problems = Vec::new();
+  for requirement in &resolver.requirements {
+    match resolver.check_requirement(requirement) {
+      Ok(()) => {}
+      Err(problem) if is_environment_package(&requirement.name) => {
+        compatibility_problems.push(problem)
+      }
+      Err(problem) => resolver.problems.push(problem),
+    }
+  }
+
+  if ignore_compatibility {
+    for problem in &compatibility_problems {
+      warn!("{}, installing anyway", problem);
+    }
+  } else {
+    resolver.problems.extend(compatibility_problems);
+  }
+
+  if !resolver.problems.is_empty() {
+    bail!(
+      "Couldn't resolve the dependencies:\n{}",
+      resolver
+        .problems
+        .iter()
+        .map(|problem| format!("  {}", problem))
+        .collect::<Vec<String>>()
+        .join("\n")
+    );
+  }
+
+  let mut steps = resolver.steps;
+  for step in &mut steps {
+    for requirement in &resolver.requirements {
+      if requirement.name == step.package.name
+        && !step.required_by.contains(&requirement.required_by)
+      {
+        step.required_by.push(requirement.required_by.clone());
+      }
+    }
+  }
+  Ok(steps)
+}
+
+fn is_environment_package(name: &str) -> bool {
+  name == GAME_PACKAGE_NAME
+    || name == NWJS_PACKAGE_NAME
+    || name == ccmoddb::CCLOADER_PACKAGE_NAME
+}
+
+impl<'a> Resolver<'a> {
+  // `requirement` is `None` for the mods requested by the user
+  fn visit(&mut self, name: &str, requirement: Option<(&str, &str)>) {
+    if let Some((req, required_by)) = requirement {
+      self.requirements.push(Requirement {
+        name: name.to_owned(),
+        req: req.to_owned(),
+        required_by: required_by.to_owned(),
+      });
+    }
+    if is_environment_package(name) {
+      return;
+    }
+
+    let req = requirement.map(|(req, _)| req);
+    match self.selected.get(name) {
+      Some(&Selection::Installed(installed)) => {
+        if !req.is_none_or(|req| satisfies(installed.version.as_deref(), req)) {
+          self.plan(name, Some(installed), requirement);
+        }
+        return;
+      }
+      Some(_) => return,
+      None => {}
+    }
+
+    let environment: &'a Environment = self.environment;
+    match environment.installed_mod(name) {
+      Some(installed)
+        if req
+          .is_none_or(|req| satisfies(installed.version.as_deref(), req)) =>
+      {
+        if !installed.enabled {
+          warn!(
+            "{} is installed, but disabled, it won't be loaded by CCLoader",
+            name
+          );
+        }
+        self.selected.insert(name.to_owned(), Selection::Installed(installed));
+      }
+      installed => self.plan(name, installed, requirement),
+    }
+  }
+
+  // selects the version from the database and visits its dependencies
+  fn plan(
+    &mut self,
+    name: &str,
+    installed: Option<&'a InstalledMod>,
+    requirement: Option<(&str, &str)>,
+  ) {
+    let package = match self.database.package(name) {
+      Some(package) => package,
+      None => {
+        // an installed copy which doesn't satisfy a requirement is reported
+        // when the requirements are checked
+        let selection = match installed {
+          Some(installed) => Selection::Installed(installed),
+          None => {
+            self.problems.push(match requirement {
+              Some((_, required_by)) => format!(
+                "{} requires {}, which couldn't be found in CCModDB",
+                required_by, name
+              ),
+              None => format!("Couldn't find {} in CCModDB", name),
+            });
+            Selection::Unavailable
+          }
+        };
+        self.selected.insert(name.to_owned(), selection);
+        return;
+      }
+    };
+    if package.mod_artifact().is_none() {
+      self.problems.push(format!(
+        "{} doesn't have any installable archive in CCModDB",
+        name
+      ));
+      self.selected.insert(name.to_owned(), Selection::Unavailable);
+      return;
+    }
+
+    self
+      .selected
+      .insert(name.to_owned(), Selection::Planned(package.version.clone()));
+    // a dependency cycle ends here because every package is selected before
+    // its dependencies are visited
+    for (dep_name, req) in &package.dependencies {
+      self.visit(dep_name, Some((req, name)));
+    }
+
+    let rel_path = match installed {
+      Some(installed) => installed.rel_path(),
+      None => MODS_DIR_PATH.join(name),
+    };
+    self.steps.push(Step {
+      package,
+      rel_path,
+      installed_version: installed.and_then(|m| m.version.clone()),
+      required_by: Vec::new(),
+    });
+  }
+
+  fn check_requirement(&self, requirement: &Requirement) -> Result<(), String> {
+    let Requirement { name, req, required_by } = requirement;
+    let parsed_req = match VersionReq::parse(req) {
+      Ok(parsed_req) => parsed_req,
+      Err(_) => {
+        warn!(
+          "{} has an invalid requirement '{}' for {}, ignoring it",
+          required_by, req, name
+        );
+        return Ok(());
+      }
+    };
+    let display_name = match name.as_str() {
+      GAME_PACKAGE_NAME => "CrossCode",
+      NWJS_PACKAGE_NAME => "nw.js",
+      ccmoddb::CCLOADER_PACKAGE_NAME => "CCLoader",
+      _ => name,
+    };
+    let unmet = |what: String| {
+      Err(format!(
+        "{} requires {} {}, but {}",
+        required_by, display_name, req, what
+      ))
+    };
+
+    match name.as_str() {
+      GAME_PACKAGE_NAME => match &self.environment.game_version {
+        Some(version) => match game::parse_game_version(version) {
+          Ok(parsed) if parsed_req.matches(&parsed) => Ok(()),
+          _ => unmet(format!("the game version is {}", version)),
+        },
+        None => unmet("the game version couldn't be determined".to_owned()),
+      },
+      // nw.js is checked only when its version is known, see
+      // `game::check_compatibility`
+      NWJS_PACKAGE_NAME => match &self.environment.nwjs_version {
+        Some(version) if !satisfies(Some(version), req) => {
+          unmet(format!("the nw.js version is {}", version))
+        }
+        _ => Ok(()),
+      },
+      ccmoddb::CCLOADER_PACKAGE_NAME => {
+        match &self.environment.ccloader_version {
+          Some(version) if satisfies(Some(version), req) => Ok(()),
+          Some(version) => unmet(format!("CCLoader v{} is installed", version)),
+          None => unmet("CCLoader isn't installed".to_owned()),
+        }
+      }
+      _ => match self.selected.get(name) {
+        Some(Selection::Installed(installed)) => {
+          if satisfies(installed.version.as_deref(), req) {
+            Ok(())
+          } else {
+            unmet(format!(
+              "v{} is installed",
+              installed.version.as_deref().unwrap_or("<unknown>")
+            ))
+          }
+        }
+        Some(Selection::Planned(version)) => {
+          if satisfies(Some(version), req) {
+            Ok(())
+          } else {
+            unmet(format!("the latest version in CCModDB is v{}", version))
+          }
+        }
+        Some(Selection::Unavailable) | None => Ok(()),
+      },
+    }
+  }
+}
+
+fn satisfies(version: Option<&str>, req: &str) -> bool {
+  let req = match VersionReq::parse(req) {
+    Ok(req) => req,
+    // invalid requirements are reported separately
+    Err(_) => return true,
+  };
+  match version.map(Version::parse) {
+    Some(Ok(version)) => req.matches(&version),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::{json, Value as JsonValue};
+
+  // a name, a version and a list of dependencies with their requirements
+  type PackageSpec<'a> = (&'a str, &'a str, &'a [(&'a str, &'a str)]);
+
+  fn database(packages: &[PackageSpec]) -> Database {
+    let mut data = serde_json::Map::new();
+    for &(name, version, dependencies) in packages {
+      let dependencies: serde_json::Map<String, JsonValue> = dependencies
+        .iter()
+        .map(|&(dep_name, req)| (dep_name.to_owned(), json!(req)))
+        .collect();
+      data.insert(
+        name.to_owned(),
+        json!({
+          "metadata": {
+            "name": name,
+            "version": version,
+            "ccmodDependencies": dependencies,
+          },
+          "installation": [{
+            "type": "modZip",
+            "url": format!("https://example.com/{}-{}.zip", name, version),
+            "source": name,
+          }],
+        }),
+      );
+    }
+    Database::from_json(JsonValue::Object(data)).unwrap()
+  }
+
+  fn environment(installed_mods: &[(&str, &str)]) -> Environment {
+    Environment {
+      game_version: Some("1.4.2-3".to_owned()),
+      nwjs_version: Some("0.44.5".to_owned()),
+      ccloader_version: Some("2.22.1".to_owned()),
+      installed_mods: installed_mods
+        .iter()
+        .map(|&(name, version)| InstalledMod {
+          dir_name: name.to_owned(),
+          name: name.to_owned(),
+          version: Some(version.to_owned()),
+          enabled: true,
+        })
+        .collect(),
+    }
+  }
+
+  fn names(steps: &[Step]) -> Vec<&str> {
+    steps.iter().map(|step| step.package.name.as_str()).collect()
+  }
+
+  fn resolve_names(
+    database: &Database,
+    environment: &Environment,
+    requested: &[&str],
+  ) -> AppResult<Vec<Step>> {
+    let requested: Vec<String> =
+      requested.iter().map(|&name| name.to_owned()).collect();
+    resolve(database, environment, &requested, false)
+  }
+
+  #[test]
+  fn orders_dependencies_before_dependents() {
+    let database = database(&[
+      ("app", "1.0.0", &[("lib", "^1.0.0"), ("util", "*")]),
+      ("lib", "1.2.0", &[("base", ">=0.1.0")]),
+      ("util", "0.3.0", &[("base", "*")]),
+      ("base", "0.2.0", &[]),
+    ]);
+    let steps = resolve_names(&database, &environment(&[]), &["app"]).unwrap();
+    assert_eq!(names(&steps), vec!["base", "lib", "util", "app"]);
+
+    let base = &steps[0];
+    assert_eq!(base.rel_path, MODS_DIR_PATH.join("base"));
+    assert_eq!(base.installed_version, None);
+    assert_eq!(base.required_by, vec!["lib", "util"]);
+    assert!(steps[3].required_by.is_empty());
+  }
+
+  #[test]
+  fn skips_installed_mods_which_satisfy_the_requirements() {
+    let database = database(&[
+      ("app", "1.0.0", &[("lib", "^1.0.0")]),
+      ("lib", "1.2.0", &[]),
+    ]);
+    let steps =
+      resolve_names(&database, &environment(&[("lib", "1.0.5")]), &["app"])
+        .unwrap();
+    assert_eq!(names(&steps), vec!["app"]);
+  }
+
+  #[test]
+  fn terminates_on_dependency_cycles() {
+    let database = database(&[
+      ("a", "1.0.0", &[("b", "^1.0.0")]),
+      ("b", "1.0.0", &[("c", "^1.0.0")]),
+      ("c", "1.0.0", &[("a", "^1.0.0")]),
+    ]);
+    let steps = resolve_names(&database, &environment(&[]), &["a"]).unwrap();
+    assert_eq!(names(&steps), vec!["c", "b", "a"]);
+    assert_eq!(steps[2].required_by, vec!["c"]);
+  }
+
+  #[test]
+  fn reports_conflicting_requirements() {
+    let database = database(&[
+      ("old-app", "1.0.0", &[("lib", "^1.0.0")]),
+      ("new-app", "1.0.0", &[("lib", "^2.0.0")]),
+      ("lib", "2.1.0", &[]),
+    ]);
+    let error =
+      resolve_names(&database, &environment(&[]), &["old-app", "new-app"])
+        .unwrap_err();
+    assert!(
+      error.contains(
+        "old-app requires lib ^1.0.0, but the latest version in CCModDB is v2.1.0"
+      ),
+      "{}",
+      error
+    );
+    assert!(!error.contains("new-app requires"), "{}", error);
+  }
+
+  #[test]
+  fn updates_installed_mods_which_are_too_old() {
+    let database = database(&[
+      ("app", "1.0.0", &[("lib", "^1.2.0"), ("extra", "^1.0.0")]),
+      ("lib", "1.3.0", &[]),
+      ("extra", "1.0.0", &[]),
+    ]);
+    let steps =
+      resolve_names(&database, &environment(&[("lib", "1.1.0")]), &["app"])
+        .unwrap();
+    // dependencies of the same mod are visited in alphabetical order
+    assert_eq!(names(&steps), vec!["extra", "lib", "app"]);
+
+    // the installed copy is replaced in place
+    assert_eq!(steps[1].installed_version.as_deref(), Some("1.1.0"));
+    assert_eq!(steps[1].rel_path, MODS_DIR_PATH.join("lib"));
+    // a mod which is only planned for installation has no installed version
+    assert_eq!(steps[0].installed_version, None);
+  }
+
+  #[test]
+  fn keeps_installed_mods_which_are_too_old_when_there_is_no_update() {
+    let outdated = database(&[
+      ("app", "1.0.0", &[("lib", "^1.2.0")]),
+      ("lib", "1.1.0", &[]),
+    ]);
+    let error =
+      resolve_names(&outdated, &environment(&[("lib", "1.0.0")]), &["app"])
+        .unwrap_err();
+    assert!(
+      error.contains(
+        "app requires lib ^1.2.0, but the latest version in CCModDB is v1.1.0"
+      ),
+      "{}",
+      error
+    );
+
+    let without_lib = database(&[("app", "1.0.0", &[("local", "^1.2.0")])]);
+    let error = resolve_names(
+      &without_lib,
+      &environment(&[("local", "1.0.0")]),
+      &["app"],
+    )
+    .unwrap_err();
+    assert!(
+      error.contains("app requires local ^1.2.0, but v1.0.0 is installed"),
+      "{}",
+      error
+    );
+  }
+
+  #[test]
+  fn updates_requested_mods() {
+    let database = database(&[
+      ("app", "2.0.0", &[("lib", "^2.0.0")]),
+      ("lib", "2.0.0", &[]),
+    ]);
+    let environment = environment(&[("app", "1.0.0"), ("lib", "1.0.0")]);
+    let steps =
+      resolve_updates(&database, &environment, &["app".to_owned()], false)
+        .unwrap();
+    assert_eq!(names(&steps), vec!["lib", "app"]);
+    assert_eq!(steps[1].installed_version.as_deref(), Some("1.0.0"));
+  }
+
+  #[test]
+  fn reports_unavailable_packages() {
+    let database = database(&[("app", "1.0.0", &[("missing-lib", "*")])]);
+
+    let error = resolve_names(&database, &environment(&[]), &["nonexistent"])
+      .unwrap_err();
+    assert!(
+      error.contains("Couldn't find nonexistent in CCModDB"),
+      "{}",
+      error
+    );
+
+    let error =
+      resolve_names(&database, &environment(&[]), &["app"]).unwrap_err();
+    assert!(
+      error.contains("app requires missing-lib, which couldn't be found"),
+      "{}",
+      error
+    );
+
+    // an installed copy is enough when the database doesn't have the mod
+    let steps = resolve_names(
+      &database,
+      &environment(&[("missing-lib", "0.1.0")]),
+      &["app"],
+    )
+    .unwrap();
+    assert_eq!(names(&steps), vec!["app"]);
+  }
+
+  #[test]
+  fn reports_packages_without_installable_archives() {
+    let data = json!({
+      "tool": {
+        "metadata": { "name": "tool", "version": "1.0.0" },
+        "installation": [{
+          "type": "externalTool",
+          "url": "https://example.com/tool.zip",
+        }],
+      },
+    });
+    let database = Database::from_json(data).unwrap();
+    let error =
+      resolve_names(&database, &environment(&[]), &["tool"]).unwrap_err();
+    assert!(
+      error.contains("tool doesn't have any installable archive"),
+      "{}",
+      error
+    );
+  }
+
+  #[test]
+  fn checks_the_game_version() {
+    let database = database(&[
+      ("new", "1.0.0", &[("crosscode", "^1.5.0")]),
+      ("current", "1.0.0", &[("crosscode", ">=1.4.0")]),
+    ]);
+    let mut environment = environment(&[]);
+
+    let error = resolve_names(&database, &environment, &["new"]).unwrap_err();
+    assert!(
+      error.contains(
+        "new requires CrossCode ^1.5.0, but the game version is 1.4.2-3"
+      ),
+      "{}",
+      error
+    );
+    // the hotfix suffix of the game version isn't a pre-release
+    assert!(resolve_names(&database, &environment, &["current"]).is_ok());
+    // compatibility problems may be ignored
+    let steps =
+      resolve(&database, &environment, &["new".to_owned()], true).unwrap();
+    assert_eq!(names(&steps), vec!["new"]);
+
+    environment.game_version = None;
+    let error =
+      resolve_names(&database, &environment, &["current"]).unwrap_err();
+    assert!(
+      error.contains("the game version couldn't be determined"),
+      "{}",
+      error
+    );
+  }
+
+  #[test]
+  fn checks_the_nwjs_version() {
+    let database = database(&[("app", "1.0.0", &[("nwjs", ">=0.50.0")])]);
+    let mut environment = environment(&[]);
+
+    let error = resolve_names(&database, &environment, &["app"]).unwrap_err();
+    assert!(
+      error.contains(
+        "app requires nw.js >=0.50.0, but the nw.js version is 0.44.5"
+      ),
+      "{}",
+      error
+    );
+
+    // nw.js is often impossible to detect, which isn't treated as a failure
+    environment.nwjs_version = None;
+    assert!(resolve_names(&database, &environment, &["app"]).is_ok());
+  }
+
+  #[test]
+  fn checks_the_ccloader_version() {
+    let database = database(&[("app", "1.0.0", &[("ccloader", "^3.0.0")])]);
+    let mut environment = environment(&[]);
+
+    let error = resolve_names(&database, &environment, &["app"]).unwrap_err();
+    assert!(
+      error.contains(
+        "app requires CCLoader ^3.0.0, but CCLoader v2.22.1 is installed"
+      ),
+      "{}",
+      error
+    );
+
+    environment.ccloader_version = None;
+    let error = resolve_names(&database, &environment, &["app"]).unwrap_err();
+    assert!(error.contains("CCLoader isn't installed"), "{}", error);
+  }
+}
@@ -0,0 +1,346 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+mod vdf;
+
+pub const CROSSCODE_APP_ID: &str = "368340";
+
+// Returns the game data directories of all CrossCode installations found in
+// the libraries of the given Steam installation.
+pub fn find_crosscode_installations(steam_dir: &Path) -> Vec<PathBuf> {
+  library_folders(steam_dir)
+    .iter()
+    .filter_map(|library_dir| {
+      find_app_install_dir(library_dir, CROSSCODE_APP_ID)
+    })
+    .map(|install_dir| game_data_dir(&install_dir))
+    .collect()
+}
+
+// the place where the game is installed into if there is no app manifest to
+// tell otherwise
+pub fn default_game_data_dir(library_dir: &Path) -> PathBuf {
+  game_data_dir(&steamapps_dir(library_dir).join("common").join("CrossCode"))
+}
+
+#[cfg(target_os = "macos")]
+fn game_data_dir(install_dir: &Path) -> PathBuf {
+  install_dir.join("CrossCode.app/Contents/Resources/app.nw")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn game_data_dir(install_dir: &Path) -> PathBuf {
+  install_dir.to_owned()
+}
+
+fn steamapps_dir(library_dir: &Path) -> PathBuf {
+  library_dir.join("steamapps")
+}
+
+// Every library has a `steamapps` directory, the list of them is kept in
+// `steamapps/libraryfolders.vdf` of the main Steam directory, which is itself
+// a library as well.
+fn library_folders(steam_dir: &Path) -> Vec<PathBuf> {
+  let mut result = vec![steam_dir.to_owned()];
+
+  let vdf_path = steamapps_dir(steam_dir).join("libraryfolders.vdf");
+  let document = match read_vdf_file(&vdf_path) {
+    Some(document) => document,
+    None => return result,
+  };
+  let folders = match document.get("libraryfolders") {
+    Some(vdf::Value::Object(folders)) => folders,
+    _ => {
+      warn!("no library folders in {}", vdf_path.display());
+      return result;
+    }
+  };
+
+  for (key, value) in folders.iter() {
+    // other keys, such as `TimeNextStatsReport` or `ContentStatsID`, aren't
+    // libraries
+    if key.parse::<u32>().is_err() {
+      continue;
+    }
+    // the old format lists just the paths, the new one has an object for every
+    // library with the path and the installed apps
+    let path = match value {
+      vdf::Value::String(path) => Some(path.as_str()),
+      vdf::Value::Object(_) => value.get("path").and_then(vdf::Value::as_str),
+    };
+    if let Some(path) = path {
+      let path = PathBuf::from(path);
+      if !result.iter().any(|p| is_same_dir(p, &path)) {
+        info!("found a Steam library at {}", path.display());
+        result.push(path);
+      }
+    }
+  }
+
+  result
+}
+
+fn find_app_install_dir(library_dir: &Path, app_id: &str) -> Option<PathBuf> {
+  let steamapps_dir = steamapps_dir(library_dir);
+  let manifest_path = steamapps_dir.join(format!("appmanifest_{}.acf", app_id));
+  if !manifest_path.is_file() {
+    return None;
+  }
+
+  let document = read_vdf_file(&manifest_path)?;
+  let install_dir_name =
+    document.get("AppState")?.get("installdir")?.as_str()?;
+  Some(steamapps_dir.join("common").join(install_dir_name))
+}
+
+fn read_vdf_file(path: &Path) -> Option<vdf::Object> {
+  info!("reading {}", path.display());
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(error) => {
+      info!("couldn't read {}: {}", path.display(), error);
+      return None;
+    }
+  };
+  let document = vdf::parse(&text);
+  if document.is_none() {
+    warn!("couldn't parse {}", path.display());
+  }
+  document
+}
+
+// paths to the same library may be spelled differently, e.g. `~/.steam/steam`
+// is usually a symlink to `~/.local/share/Steam`
+pub fn is_same_dir(a: &Path, b: &Path) -> bool {
+  match (fs::canonicalize(a), fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => a == b,
+  }
+}
+
+// Returns the Steam installations together with a short description of each
+// one, which is shown to the user.
+#[cfg(target_os = "linux")]
+pub fn steam_dirs() -> Vec<(PathBuf, &'static str)> {
+  let mut candidates = Vec::with_capacity(4);
+  if let Some(home) = dirs::home_dir() {
+    candidates.push((home.join(".steam/steam"), "Steam"));
+    candidates.push((home.join(".local/share/Steam"), "Steam"));
+    // the Flatpak version of Steam keeps its files in the sandbox directory
+    let flatpak_home = home.join(".var/app/com.valvesoftware.Steam");
+    candidates
+      .push((flatpak_home.join(".local/share/Steam"), "Steam (Flatpak)"));
+    candidates.push((flatpak_home.join(".steam/steam"), "Steam (Flatpak)"));
+  }
+
+  let mut result: Vec<(PathBuf, &'static str)> =
+    Vec::with_capacity(candidates.len());
+  for (path, source) in candidates {
+    if path.is_dir() && !result.iter().any(|(p, _)| is_same_dir(p, &path)) {
+      result.push((path, source));
+    }
+  }
+  result
+}
+
+#[cfg(target_os = "macos")]
+pub fn steam_dirs() -> Vec<(PathBuf, &'static str)> {
+  let mut result = Vec::with_capacity(1);
+  if let Some(home) = dirs::home_dir() {
+    result.push((home.join("Library/Application Support/Steam"), "Steam"));
+  }
+  result
+}
+
+#[cfg(target_os = "windows")]
+pub fn steam_dirs() -> Vec<(PathBuf, &'static str)> {
+  let mut result = Vec::with_capacity(3);
+  if let Some(path) = steam_dir_from_registry() {
+    result.push((path, "Steam"));
+  }
+  for path in &["C:\\Program Files (x86)\\Steam", "C:\\Program Files\\Steam"] {
+    let path = PathBuf::from(path);
+    if !result.iter().any(|(p, _)| is_same_dir(p, &path)) {
+      result.push((path, "Steam"));
+    }
+  }
+  result
+}
+
+// Steam remembers where it has been installed to in the registry, which is
+// the only way to find it when it isn't in `Program Files`
+#[cfg(target_os = "windows")]
+fn steam_dir_from_registry() -> Option<PathBuf> {
+  use std::ffi::OsString;
+  use std::ptr::null_mut;
+
+  use winapi::shared::minwindef::DWORD;
+  use winapi::shared::winerror::ERROR_SUCCESS;
+  use winapi::um::winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ};
+  use wio::wide::{FromWide, ToWide};
+
+  let sub_key = "Software\\Valve\\Steam".to_wide_null();
+  let value_name = "SteamPath".to_wide_null();
+
+  let mut size: DWORD = 0;
+  let status = unsafe {
+    RegGetValueW(
+      HKEY_CURRENT_USER,
+      sub_key.as_ptr(),
+      value_name.as_ptr(),
+      RRF_RT_REG_SZ,
+      null_mut(),
+      null_mut(),
+      &mut size,
+    )
+  };
+  if status as DWORD != ERROR_SUCCESS || size == 0 {
+    return None;
+  }
+
+  let mut buf: Vec<u16> = vec![0; size as usize / 2];
+  let status = unsafe {
+    RegGetValueW(
+      HKEY_CURRENT_USER,
+      sub_key.as_ptr(),
+      value_name.as_ptr(),
+      RRF_RT_REG_SZ,
+      null_mut(),
+      buf.as_mut_ptr() as *mut _,
+      &mut size,
+    )
+  };
+  if status as DWORD != ERROR_SUCCESS {
+    return None;
+  }
+
+  let path = PathBuf::from(OsString::from_wide_null(&buf));
+  info!("Steam is installed at {} according to the registry", path.display());
+  Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a fresh directory for every test, the tests run in parallel
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+      "ccloader-installer-test-{}-steam-{}",
+      std::process::id(),
+      name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  // paths are escaped the same way as Steam does it on Windows
+  fn vdf_path(path: &Path) -> String {
+    path.to_str().unwrap().replace('\\', "\\\\")
+  }
+
+  fn write_steamapps_file(library_dir: &Path, name: &str, contents: &str) {
+    let dir = steamapps_dir(library_dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(name), contents).unwrap();
+  }
+
+  fn write_app_manifest(library_dir: &Path, install_dir_name: &str) {
+    write_steamapps_file(
+      library_dir,
+      "appmanifest_368340.acf",
+      &format!(
+        "\"AppState\"\n{{\n\t\"appid\"\t\t\"368340\"\n\t\"name\"\t\t\"CrossCode\"\n\t\"installdir\"\t\t\"{}\"\n}}\n",
+        install_dir_name
+      ),
+    );
+  }
+
+  #[test]
+  fn reads_old_library_folders_format() {
+    let root = test_dir("old-format");
+    let steam_dir = root.join("Steam");
+    let library_dir = root.join("SteamLibrary");
+    fs::create_dir_all(&library_dir).unwrap();
+    write_steamapps_file(
+      &steam_dir,
+      "libraryfolders.vdf",
+      &format!(
+        "\"LibraryFolders\"\n{{\n\t\"TimeNextStatsReport\"\t\t\"1596234567\"\n\t\"ContentStatsID\"\t\t\"-1234567890\"\n\t\"1\"\t\t\"{}\"\n}}\n",
+        vdf_path(&library_dir)
+      ),
+    );
+
+    assert_eq!(library_folders(&steam_dir), vec![steam_dir, library_dir]);
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn reads_new_library_folders_format() {
+    let root = test_dir("new-format");
+    let steam_dir = root.join("Steam");
+    let library_dir = root.join("SteamLibrary");
+    fs::create_dir_all(&library_dir).unwrap();
+    // the main Steam directory is listed as a library as well
+    write_steamapps_file(
+      &steam_dir,
+      "libraryfolders.vdf",
+      &format!(
+        "\"libraryfolders\"\n{{\n\t\"contentstatsid\"\t\t\"-1234567890\"\n\t\"0\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"apps\"\n\t\t{{\n\t\t\t\"228980\"\t\t\"123\"\n\t\t}}\n\t}}\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"label\"\t\t\"\"\n\t\t\"apps\"\n\t\t{{\n\t\t\t\"368340\"\t\t\"456\"\n\t\t}}\n\t}}\n}}\n",
+        vdf_path(&steam_dir),
+        vdf_path(&library_dir)
+      ),
+    );
+
+    assert_eq!(library_folders(&steam_dir), vec![steam_dir, library_dir]);
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn falls_back_to_the_main_library() {
+    let root = test_dir("no-library-folders");
+    let steam_dir = root.join("Steam");
+    fs::create_dir_all(&steam_dir).unwrap();
+    assert_eq!(library_folders(&steam_dir), vec![steam_dir.clone()]);
+
+    write_steamapps_file(
+      &steam_dir,
+      "libraryfolders.vdf",
+      "\"libraryfolders\"\n{",
+    );
+    assert_eq!(library_folders(&steam_dir), vec![steam_dir]);
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn finds_the_game_by_its_app_manifest() {
+    let root = test_dir("app-manifest");
+    let steam_dir = root.join("Steam");
+    let library_dir = root.join("SteamLibrary");
+    write_steamapps_file(
+      &steam_dir,
+      "libraryfolders.vdf",
+      &format!(
+        "\"libraryfolders\"\n{{\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n}}\n",
+        vdf_path(&library_dir)
+      ),
+    );
+    write_app_manifest(&library_dir, "CrossCode Renamed");
+
+    assert_eq!(find_app_install_dir(&steam_dir, CROSSCODE_APP_ID), None);
+    let install_dir =
+      steamapps_dir(&library_dir).join("common").join("CrossCode Renamed");
+    assert_eq!(
+      find_app_install_dir(&library_dir, CROSSCODE_APP_ID),
+      Some(install_dir.clone())
+    );
+    assert_eq!(
+      find_crosscode_installations(&steam_dir),
+      vec![game_data_dir(&install_dir)]
+    );
+    fs::remove_dir_all(&root).unwrap();
+  }
+}
@@ -0,0 +1,399 @@
+// A parser for Valve's KeyValues text format (also known as VDF), which Steam
+// uses for `libraryfolders.vdf` and `appmanifest_*.acf` files. See
+// https://developer.valvesoftware.com/wiki/KeyValues for the description.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+  String(String),
+  Object(Object),
+}
+
+// keys may repeat and their order is significant for some files, so a list of
+// pairs is used instead of a map
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Object(Vec<(String, Value)>);
+
+impl Value {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::String(s) => Some(s),
+      Value::Object(_) => None,
+    }
+  }
+
+  pub fn as_object(&self) -> Option<&Object> {
+    match self {
+      Value::String(_) => None,
+      Value::Object(o) => Some(o),
+    }
+  }
+
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    self.as_object()?.get(key)
+  }
+}
+
+impl Object {
+  // keys are case-insensitive, e.g. old Steam versions write `LibraryFolders`
+  // and new ones write `libraryfolders`
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    self.0.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+    self.0.iter().map(|(k, v)| (k.as_str(), v))
+  }
+}
+
+// returns the top-level pairs of the document
+pub fn parse(text: &str) -> Option<Object> {
+  let mut p = Parser { chars: text.chars().peekable() };
+  let object = p.parse_pairs()?;
+  match p.next_token()? {
+    Token::End => Some(object),
+    _ => None,
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+  String(String),
+  OpenBrace,
+  CloseBrace,
+  End,
+}
+
+struct Parser<'a> {
+  chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+  // parses pairs until a closing brace or the end of the input, which is left
+  // for the caller to consume
+  fn parse_pairs(&mut self) -> Option<Object> {
+    let mut pairs = Vec::new();
+    loop {
+      self.skip_whitespace_and_comments();
+      match self.chars.peek() {
+        None | Some('}') => return Some(Object(pairs)),
+        _ => {}
+      }
+
+      let key = match self.next_token()? {
+        Token::String(s) => s,
+        _ => return None,
+      };
+      let value = match self.next_token()? {
+        Token::String(s) => Value::String(s),
+        Token::OpenBrace => {
+          let object = self.parse_pairs()?;
+          if self.next_token()? != Token::CloseBrace {
+            return None;
+          }
+          Value::Object(object)
+        }
+        _ => return None,
+      };
+      self.skip_conditional();
+      pairs.push((key, value));
+    }
+  }
+
+  fn next_token(&mut self) -> Option<Token> {
+    self.skip_whitespace_and_comments();
+    Some(match self.chars.next() {
+      None => Token::End,
+      Some('{') => Token::OpenBrace,
+      Some('}') => Token::CloseBrace,
+      Some('"') => Token::String(self.parse_quoted_string()?),
+      Some(c) => {
+        let mut s = c.to_string();
+        while let Some(&c) = self.chars.peek() {
+          if c.is_whitespace() || c == '"' || c == '{' || c == '}' {
+            break;
+          }
+          s.push(c);
+          self.chars.next();
+        }
+        Token::String(s)
+      }
+    })
+  }
+
+  // the opening quote has already been consumed
+  fn parse_quoted_string(&mut self) -> Option<String> {
+    let mut s = String::new();
+    loop {
+      match self.chars.next()? {
+        '"' => return Some(s),
+        '\\' => match self.chars.next()? {
+          'n' => s.push('\n'),
+          't' => s.push('\t'),
+          // this also covers `\\` and `\"`, which are the escapes Steam
+          // actually writes (mostly in Windows paths)
+          c => s.push(c),
+        },
+        c => s.push(c),
+      }
+    }
+  }
+
+  fn skip_whitespace_and_comments(&mut self) {
+    loop {
+      match self.chars.peek() {
+        Some(c) if c.is_whitespace() => {
+          self.chars.next();
+        }
+        Some('/') => {
+          let mut lookahead = self.chars.clone();
+          lookahead.next();
+          if lookahead.peek() != Some(&'/') {
+            return;
+          }
+          for c in &mut self.chars {
+            if c == '\n' {
+              break;
+            }
+          }
+        }
+        _ => return,
+      }
+    }
+  }
+
+  // conditionals such as `[$WIN32]` may follow a value, they are ignored
+  fn skip_conditional(&mut self) {
+    while let Some(c) = self.chars.peek() {
+      if *c == '\n' || !c.is_whitespace() {
+        break;
+      }
+      self.chars.next();
+    }
+    if self.chars.peek() == Some(&'[') {
+      for c in &mut self.chars {
+        if c == ']' {
+          break;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn get_str<'a>(object: &'a Object, path: &[&str]) -> Option<&'a str> {
+    let (last, parents) = path.split_last()?;
+    let mut object = object;
+    for key in parents {
+      object = object.get(key)?.as_object()?;
+    }
+    object.get(last)?.as_str()
+  }
+
+  #[test]
+  fn parses_old_library_folders_format() {
+    let document = parse(
+      r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1596234567"
+	"ContentStatsID"		"-1234567890123456789"
+	"1"		"D:\\Steam"
+	"2"		"E:\\Games\\SteamLibrary"
+}
+"#,
+    )
+    .unwrap();
+    assert_eq!(get_str(&document, &["libraryfolders", "1"]), Some(r"D:\Steam"));
+    assert_eq!(
+      get_str(&document, &["LibraryFolders", "2"]),
+      Some(r"E:\Games\SteamLibrary")
+    );
+  }
+
+  #[test]
+  fn parses_new_library_folders_format() {
+    let document = parse(
+      r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"337762236"
+			"368340"		"2144276134"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+	}
+}
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+      get_str(&document, &["libraryfolders", "0", "path"]),
+      Some("/home/user/.local/share/Steam")
+    );
+    assert_eq!(get_str(&document, &["libraryfolders", "0", "label"]), Some(""));
+    assert_eq!(
+      get_str(&document, &["libraryfolders", "0", "apps", "368340"]),
+      Some("2144276134")
+    );
+    assert_eq!(
+      get_str(&document, &["libraryfolders", "1", "path"]),
+      Some("/mnt/games/SteamLibrary")
+    );
+  }
+
+  #[test]
+  fn keeps_the_order_of_repeated_keys() {
+    let document = parse(r#""a" "1" "b" "2" "a" "3""#).unwrap();
+    let pairs: Vec<(&str, Option<&str>)> =
+      document.iter().map(|(k, v)| (k, v.as_str())).collect();
+    assert_eq!(
+      pairs,
+      vec![("a", Some("1")), ("b", Some("2")), ("a", Some("3"))]
+    );
+    assert_eq!(get_str(&document, &["a"]), Some("1"));
+  }
+
+  #[test]
+  fn unescapes_quoted_strings() {
+    let document = parse(
+      r#""path" "C:\\Program Files (x86)\\Steam" "name" "say \"hi\"\tnow\n""#,
+    )
+    .unwrap();
+    assert_eq!(
+      get_str(&document, &["path"]),
+      Some(r"C:\Program Files (x86)\Steam")
+    );
+    assert_eq!(get_str(&document, &["name"]), Some("say \"hi\"\tnow\n"));
+  }
+
+  #[test]
+  fn parses_unquoted_tokens() {
+    let document = parse("AppState { appid 368340 name CrossCode }").unwrap();
+    assert_eq!(get_str(&document, &["AppState", "appid"]), Some("368340"));
+    assert_eq!(get_str(&document, &["AppState", "name"]), Some("CrossCode"));
+  }
+
+  #[test]
+  fn skips_comments() {
+    let document = parse(
+      r#"
+// a comment before the document
+"AppState" // a comment after a key
+{
+	// a comment inside of an object
+	"installdir"		"CrossCode" // a comment after a value
+	"url"		"https://example.com/a//b"
+}
+// a comment at the end without a newline"#,
+    )
+    .unwrap();
+    assert_eq!(
+      get_str(&document, &["AppState", "installdir"]),
+      Some("CrossCode")
+    );
+    assert_eq!(
+      get_str(&document, &["AppState", "url"]),
+      Some("https://example.com/a//b")
+    );
+  }
+
+  #[test]
+  fn skips_conditionals() {
+    let document = parse(
+      r#"
+"Settings"
+{
+	"font"		"Tahoma"	[$WIN32]
+	"font"		"Helvetica"	[$OSX]
+	"size"		"12"	[!$X360 && !$PS3]
+	"colors"
+	{
+		"text"		"white"
+	}	[$WIN32]
+	"last"		"value"
+}
+"#,
+    )
+    .unwrap();
+    let settings = document.get("Settings").unwrap().as_object().unwrap();
+    let fonts: Vec<&str> = settings
+      .iter()
+      .filter(|(k, _)| *k == "font")
+      .filter_map(|(_, v)| v.as_str())
+      .collect();
+    assert_eq!(fonts, vec!["Tahoma", "Helvetica"]);
+    assert_eq!(get_str(settings, &["size"]), Some("12"));
+    assert_eq!(get_str(settings, &["colors", "text"]), Some("white"));
+    assert_eq!(get_str(settings, &["last"]), Some("value"));
+  }
+
+  #[test]
+  fn parses_empty_documents() {
+    assert_eq!(parse(""), Some(Object::default()));
+    assert_eq!(parse("  \n// only a comment\n"), Some(Object::default()));
+  }
+
+  #[test]
+  fn rejects_malformed_input() {
+    let inputs = [
+      // unterminated string
+      r#""libraryfolders" { "0" { "path" "D:\\Steam"#,
+      // a backslash at the very end
+      r#""path" "D:\"#,
+      // missing closing braces
+      r#""libraryfolders" { "0" { "path" "D:\\Steam" }"#,
+      r#""libraryfolders" {"#,
+      // a key without a value
+      r#""libraryfolders" { "0" { "path" } }"#,
+      r#""libraryfolders""#,
+      // unbalanced closing braces
+      r#""a" "b" }"#,
+      r#"}"#,
+      r#""a" { } }"#,
+      // an object where a key is expected
+      r#"{ "a" "b" }"#,
+      r#""a" { { "b" "c" } }"#,
+    ];
+    for input in &inputs {
+      assert_eq!(parse(input), None, "{:?} should be rejected", input);
+    }
+  }
+
+  #[test]
+  fn rejects_truncated_files() {
+    let document = r#""libraryfolders"
+{
+	"0"
+	{
+		"path"		"C:\\Program Files (x86)\\Steam"
+		"apps"
+		{
+			"228980"		"337762236"
+		}
+	}
+}
+"#;
+    assert!(parse(document).is_some());
+    // every prefix which cuts the document before its last closing brace is
+    // incomplete, none of them may panic
+    let end = document.rfind('}').unwrap();
+    for (index, _) in document.char_indices().filter(|&(i, _)| i <= end) {
+      let prefix = &document[..index];
+      if prefix.trim().is_empty() {
+        continue;
+      }
+      assert_eq!(parse(prefix), None, "{:?} should be rejected", prefix);
+    }
+  }
+}
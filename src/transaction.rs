@@ -0,0 +1,597 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{error, info, warn};
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::{Result as AppResult, ResultExt};
+
+// the directory is created inside of the game data directory, so that renames
+// between it and the rest of the game files stay on the same filesystem
+pub const STAGING_DIR_NAME: &str = ".ccloader-installer-staging";
+// a copy of the journal is kept on disk, so that the changes of a run which
+// was killed or crashed can be rolled back by the next one
+const JOURNAL_FILE_NAME: &str = "journal.json";
+const BACKUPS_DIR_NAME: &str = "backups";
+const SNAPSHOTS_DIR_NAME: &str = "snapshots";
+
+#[derive(Debug)]
+enum Operation {
+  CreatedDir { path: PathBuf },
+  MovedIntoPlace { path: PathBuf },
+  MovedAside { path: PathBuf, backup_path: PathBuf },
+  // `snapshot_path` is `None` if the file didn't exist
+  ModifiedFile { path: PathBuf, snapshot_path: Option<PathBuf> },
+}
+
+// A set of filesystem changes inside of the game data directory which is
+// either applied completely or not at all. Every change is recorded in a
+// journal, and unless `commit` is called the journal is replayed backwards
+// when the transaction is dropped, e.g. when an error is propagated with `?`.
+#[derive(Debug)]
+pub struct Transaction {
+  root_dir: PathBuf,
+  staging_dir: PathBuf,
+  backups_dir: PathBuf,
+  snapshots_dir: PathBuf,
+  journal: Vec<Operation>,
+  finished: bool,
+}
+
+impl Transaction {
+  pub fn begin(root_dir: &Path) -> AppResult<Self> {
+    let staging_dir = root_dir.join(STAGING_DIR_NAME);
+    if staging_dir.exists() {
+      Self::recover(root_dir, &staging_dir)?;
+    }
+
+    let transaction = Self::new(root_dir, Vec::new());
+    for dir in &[&transaction.backups_dir, &transaction.snapshots_dir] {
+      fs::create_dir_all(dir).with_context(|_| {
+        format!("couldn't create directory '{}'", dir.display())
+      })?;
+    }
+    transaction.write_journal(false)?;
+    Ok(transaction)
+  }
+
+  fn new(root_dir: &Path, journal: Vec<Operation>) -> Self {
+    let staging_dir = root_dir.join(STAGING_DIR_NAME);
+    Self {
+      root_dir: root_dir.to_owned(),
+      backups_dir: staging_dir.join(BACKUPS_DIR_NAME),
+      snapshots_dir: staging_dir.join(SNAPSHOTS_DIR_NAME),
+      staging_dir,
+      journal,
+      finished: false,
+    }
+  }
+
+  // Rolls back the changes of an interrupted run. The files it has moved
+  // aside may be the only copies of the previous installation, so they are
+  // never deleted, the user is asked to deal with them instead if they can't
+  // be put back.
+  fn recover(root_dir: &Path, staging_dir: &Path) -> AppResult<()> {
+    warn!(
+      "{} was left over by an interrupted run, rolling back its changes",
+      staging_dir.display()
+    );
+    let journal_path = staging_dir.join(JOURNAL_FILE_NAME);
+    let journal = match fs::read(&journal_path) {
+      Ok(bytes) => {
+        let journal = serde_json::from_slice(&bytes)
+          .ok()
+          .and_then(|data| journal_from_json(root_dir, &data));
+        if journal.is_none() {
+          warn!("the journal '{}' is corrupted", journal_path.display());
+        }
+        journal
+      }
+      Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+      Err(e) => {
+        return Err(e).with_context(|_| {
+          format!("couldn't read '{}'", journal_path.display())
+        })
+      }
+    };
+
+    match journal {
+      // every change is in place, the backups aren't needed anymore
+      Some((true, _)) => {
+        info!("the interrupted run had already committed its changes")
+      }
+      Some((false, operations)) => {
+        if !Self::new(root_dir, operations).rollback() {
+          bail!(
+            "Couldn't roll back the changes of an interrupted run, the files it has moved aside are kept in '{}' and listed in '{}'. Please put them back or delete them, then delete '{}'",
+            staging_dir.join(BACKUPS_DIR_NAME).display(),
+            journal_path.display(),
+            staging_dir.display()
+          );
+        }
+        return Ok(());
+      }
+      None if !is_empty_dir(&staging_dir.join(BACKUPS_DIR_NAME)) => bail!(
+        "An interrupted run has left files in '{}' without a readable journal, so they can't be put back automatically. Please put them back or delete them, then delete '{}'",
+        staging_dir.join(BACKUPS_DIR_NAME).display(),
+        staging_dir.display()
+      ),
+      None => {}
+    }
+
+    fs::remove_dir_all(staging_dir).with_context(|_| {
+      format!("couldn't delete directory '{}'", staging_dir.display())
+    })
+  }
+
+  // A scratch directory for preparing files which are later moved into place
+  // with `install`. It is deleted when the transaction finishes.
+  pub fn staging_dir(&self) -> &Path {
+    &self.staging_dir
+  }
+
+  pub fn create_dir_all(&mut self, rel_path: &Path) -> AppResult<()> {
+    let path = self.root_dir.join(rel_path);
+    let mut missing_dirs: Vec<PathBuf> = path
+      .ancestors()
+      .take_while(|p| !p.exists())
+      .map(Path::to_owned)
+      .collect();
+    fs::create_dir_all(&path).with_context(|_| {
+      format!("couldn't create directory '{}'", rel_path.display())
+    })?;
+    // the outermost directory is created first
+    while let Some(path) = missing_dirs.pop() {
+      self.journal.push(Operation::CreatedDir { path });
+    }
+    self.write_journal(false)
+  }
+
+  // Moves a prepared file or directory to `rel_path`. Anything that already
+  // exists there is moved aside first and put back on rollback.
+  pub fn install(
+    &mut self,
+    prepared_path: &Path,
+    rel_path: &Path,
+  ) -> AppResult<()> {
+    let path = self.root_dir.join(rel_path);
+    if path.exists() {
+      info!("replacing {}", rel_path.display());
+      self.remove(rel_path)?;
+    } else {
+      info!("installing {}", rel_path.display());
+    }
+
+    // renames are recorded before they are done, undoing the ones which
+    // haven't happened is a no-op
+    self.journal.push(Operation::MovedIntoPlace { path: path.clone() });
+    self.write_journal(false)?;
+    if let Err(e) = fs::rename(prepared_path, &path) {
+      self.journal.pop();
+      return Err(e).with_context(|_| {
+        format!("couldn't install '{}'", rel_path.display())
+      });
+    }
+    Ok(())
+  }
+
+  pub fn remove(&mut self, rel_path: &Path) -> AppResult<()> {
+    let path = self.root_dir.join(rel_path);
+    let backup_path = self.backups_dir.join(self.journal.len().to_string());
+    self
+      .move_aside(path, backup_path)
+      .with_context(|_| format!("couldn't remove '{}'", rel_path.display()))
+  }
+
+  // Moves a file or directory to `backup_rel_path`, where it is left after the
+  // transaction is committed.
+  pub fn back_up(
+    &mut self,
+    rel_path: &Path,
+    backup_rel_path: &Path,
+  ) -> AppResult<()> {
+    if let Some(parent) = backup_rel_path.parent() {
+      self.create_dir_all(parent)?;
+    }
+    let path = self.root_dir.join(rel_path);
+    let backup_path = self.root_dir.join(backup_rel_path);
+    info!("backing up {} to {}", rel_path.display(), backup_rel_path.display());
+    self
+      .move_aside(path, backup_path)
+      .with_context(|_| format!("couldn't back up '{}'", rel_path.display()))
+  }
+
+  fn move_aside(
+    &mut self,
+    path: PathBuf,
+    backup_path: PathBuf,
+  ) -> AppResult<()> {
+    self.journal.push(Operation::MovedAside {
+      path: path.clone(),
+      backup_path: backup_path.clone(),
+    });
+    self.write_journal(false)?;
+    if let Err(e) = fs::rename(&path, &backup_path) {
+      self.journal.pop();
+      return Err(e.to_string());
+    }
+    Ok(())
+  }
+
+  // Remembers the current contents of a file (or the fact that it doesn't
+  // exist) before it gets modified in place.
+  pub fn snapshot_file(&mut self, rel_path: &Path) -> AppResult<()> {
+    let path = self.root_dir.join(rel_path);
+    let snapshot_path = match fs::read(&path) {
+      Ok(bytes) => {
+        let snapshot_path =
+          self.snapshots_dir.join(self.journal.len().to_string());
+        fs::write(&snapshot_path, bytes).with_context(|_| {
+          format!("couldn't make a copy of '{}'", rel_path.display())
+        })?;
+        Some(snapshot_path)
+      }
+      Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+      Err(e) => {
+        return Err(e)
+          .with_context(|_| format!("couldn't read '{}'", rel_path.display()))
+      }
+    };
+    self.journal.push(Operation::ModifiedFile { path, snapshot_path });
+    self.write_journal(false)
+  }
+
+  pub fn commit(mut self) {
+    info!("committing the changes");
+    self.finished = true;
+    // at this point all changes have been applied, so failing to clean up is
+    // not a reason to report the whole operation as failed
+    if let Err(e) = self.write_journal(true) {
+      warn!("couldn't mark the journal as committed: {}", e);
+      return;
+    }
+    if let Err(e) = fs::remove_dir_all(&self.staging_dir) {
+      warn!(
+        "couldn't delete directory '{}': {}",
+        self.staging_dir.display(),
+        e
+      );
+    }
+  }
+
+  // returns `false` if some of the changes couldn't be undone, the staging
+  // directory is kept in that case
+  fn rollback(&mut self) -> bool {
+    warn!("rolling back the changes");
+    self.finished = true;
+
+    // errors are only logged here because rolling back is already a part of
+    // error handling, the best I can do is to restore as much as possible
+    let mut failed = Vec::new();
+    while let Some(operation) = self.journal.pop() {
+      if let Err(e) = Self::undo(&operation) {
+        error!("couldn't undo {:?}: {}", operation, e);
+        failed.push(operation);
+      }
+      // undone operations are dropped from the journal on disk right away,
+      // replaying them again after another interruption could delete the
+      // files which have just been put back
+      let remaining = self.journal.iter().chain(failed.iter().rev());
+      if let Err(e) = self.write_operations(remaining, false) {
+        error!("{}", e);
+      }
+    }
+
+    if !failed.is_empty() || !is_empty_dir(&self.backups_dir) {
+      error!(
+        "some changes couldn't be rolled back, the files which were moved aside are kept in '{}'",
+        self.backups_dir.display()
+      );
+      failed.reverse();
+      self.journal = failed;
+      return false;
+    }
+
+    if let Err(e) = fs::remove_dir_all(&self.staging_dir) {
+      error!(
+        "couldn't delete directory '{}': {}",
+        self.staging_dir.display(),
+        e
+      );
+    }
+    true
+  }
+
+  fn undo(operation: &Operation) -> io::Result<()> {
+    match operation {
+      Operation::CreatedDir { path } => ignore_not_found(fs::remove_dir(path)),
+      Operation::MovedIntoPlace { path } => {
+        if path.is_dir() {
+          fs::remove_dir_all(path)
+        } else {
+          ignore_not_found(fs::remove_file(path))
+        }
+      }
+      Operation::MovedAside { path, backup_path } => {
+        // the rename may have never happened or has already been undone
+        if fs::symlink_metadata(backup_path).is_err() {
+          return Ok(());
+        }
+        fs::rename(backup_path, path)
+      }
+      Operation::ModifiedFile { path, snapshot_path: Some(snapshot_path) } => {
+        fs::write(path, fs::read(snapshot_path)?)
+      }
+      Operation::ModifiedFile { path, snapshot_path: None } => {
+        ignore_not_found(fs::remove_file(path))
+      }
+    }
+  }
+
+  fn write_journal(&self, committed: bool) -> AppResult<()> {
+    self.write_operations(self.journal.iter(), committed)
+  }
+
+  // the journal is replaced with a rename, so that an interruption never
+  // leaves a half-written one behind
+  fn write_operations<'a>(
+    &self,
+    operations: impl Iterator<Item = &'a Operation>,
+    committed: bool,
+  ) -> AppResult<()> {
+    let data = journal_to_json(&self.root_dir, operations, committed)?;
+    let journal_path = self.staging_dir.join(JOURNAL_FILE_NAME);
+    let tmp_path = journal_path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(&data).unwrap())
+      .and_then(|()| fs::rename(&tmp_path, &journal_path))
+      .with_context(|_| {
+        format!("couldn't write the journal '{}'", journal_path.display())
+      })
+  }
+}
+
+impl Drop for Transaction {
+  fn drop(&mut self) {
+    if !self.finished {
+      self.rollback();
+    }
+  }
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+  match result {
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+    result => result,
+  }
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+  match fs::read_dir(path) {
+    Ok(mut entries) => entries.next().is_none(),
+    Err(e) => e.kind() == io::ErrorKind::NotFound,
+  }
+}
+
+// paths are stored relative to the game data directory
+fn journal_to_json<'a>(
+  root_dir: &Path,
+  operations: impl Iterator<Item = &'a Operation>,
+  committed: bool,
+) -> AppResult<JsonValue> {
+  let path_to_json = |path: &Path| -> AppResult<JsonValue> {
+    path
+      .strip_prefix(root_dir)
+      .ok()
+      .and_then(Path::to_str)
+      .map(|s| JsonValue::String(s.to_owned()))
+      .ok_or_else(|| {
+        format!("couldn't record '{}' in the journal", path.display())
+      })
+  };
+
+  let mut operations_data = Vec::new();
+  for operation in operations {
+    operations_data.push(match operation {
+      Operation::CreatedDir { path } => {
+        json!({ "type": "created_dir", "path": path_to_json(path)? })
+      }
+      Operation::MovedIntoPlace { path } => {
+        json!({ "type": "moved_into_place", "path": path_to_json(path)? })
+      }
+      Operation::MovedAside { path, backup_path } => json!({
+        "type": "moved_aside",
+        "path": path_to_json(path)?,
+        "backup_path": path_to_json(backup_path)?,
+      }),
+      Operation::ModifiedFile { path, snapshot_path } => json!({
+        "type": "modified_file",
+        "path": path_to_json(path)?,
+        "snapshot_path": match snapshot_path {
+          Some(snapshot_path) => path_to_json(snapshot_path)?,
+          None => JsonValue::Null,
+        },
+      }),
+    });
+  }
+  Ok(json!({ "committed": committed, "operations": operations_data }))
+}
+
+fn journal_from_json(
+  root_dir: &Path,
+  data: &JsonValue,
+) -> Option<(bool, Vec<Operation>)> {
+  let path_from_json = |value: &JsonValue| Some(root_dir.join(value.as_str()?));
+
+  let mut operations = Vec::new();
+  for operation_data in data["operations"].as_array()? {
+    let path = path_from_json(&operation_data["path"])?;
+    operations.push(match operation_data["type"].as_str()? {
+      "created_dir" => Operation::CreatedDir { path },
+      "moved_into_place" => Operation::MovedIntoPlace { path },
+      "moved_aside" => Operation::MovedAside {
+        path,
+        backup_path: path_from_json(&operation_data["backup_path"])?,
+      },
+      "modified_file" => Operation::ModifiedFile {
+        path,
+        snapshot_path: match &operation_data["snapshot_path"] {
+          JsonValue::Null => None,
+          value => Some(path_from_json(value)?),
+        },
+      },
+      _ => return None,
+    });
+  }
+  Some((data["committed"].as_bool()?, operations))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a fresh directory for every test, the tests run in parallel
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+      "ccloader-installer-test-{}-transaction-{}",
+      std::process::id(),
+      name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+  }
+
+  // every kind of operation: `old.txt` is replaced, `gone.txt` is removed,
+  // `new/dir` is created, `package.json` is modified and `created.json` is
+  // written from scratch
+  fn make_changes(root: &Path, transaction: &mut Transaction) {
+    let prepared = transaction.staging_dir().join("old.txt");
+    fs::write(&prepared, "new contents").unwrap();
+    transaction.install(&prepared, Path::new("old.txt")).unwrap();
+    transaction.remove(Path::new("gone.txt")).unwrap();
+    transaction.create_dir_all(Path::new("new/dir")).unwrap();
+    transaction.snapshot_file(Path::new("package.json")).unwrap();
+    fs::write(root.join("package.json"), "{\"main\":\"ccloader\"}").unwrap();
+    transaction.snapshot_file(Path::new("created.json")).unwrap();
+    fs::write(root.join("created.json"), "{}").unwrap();
+  }
+
+  fn set_up(name: &str) -> PathBuf {
+    let root = test_dir(name);
+    fs::write(root.join("old.txt"), "old contents").unwrap();
+    fs::write(root.join("gone.txt"), "removed").unwrap();
+    fs::write(root.join("package.json"), "{\"main\":\"game\"}").unwrap();
+    root
+  }
+
+  fn assert_unchanged(root: &Path) {
+    assert_eq!(read(&root.join("old.txt")), "old contents");
+    assert_eq!(read(&root.join("gone.txt")), "removed");
+    assert_eq!(read(&root.join("package.json")), "{\"main\":\"game\"}");
+    assert!(!root.join("created.json").exists());
+    assert!(!root.join("new").exists());
+  }
+
+  #[test]
+  fn rolls_back_when_dropped() {
+    let root = set_up("drop");
+    let mut transaction = Transaction::begin(&root).unwrap();
+    make_changes(&root, &mut transaction);
+    assert_eq!(read(&root.join("old.txt")), "new contents");
+    assert!(!root.join("gone.txt").exists());
+    drop(transaction);
+
+    assert_unchanged(&root);
+    assert!(!root.join(STAGING_DIR_NAME).exists());
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn commit_keeps_the_changes_and_cleans_up() {
+    let root = set_up("commit");
+    let mut transaction = Transaction::begin(&root).unwrap();
+    make_changes(&root, &mut transaction);
+    transaction.commit();
+
+    assert_eq!(read(&root.join("old.txt")), "new contents");
+    assert!(!root.join("gone.txt").exists());
+    assert_eq!(read(&root.join("package.json")), "{\"main\":\"ccloader\"}");
+    assert!(root.join("created.json").is_file());
+    assert!(root.join("new/dir").is_dir());
+    assert!(!root.join(STAGING_DIR_NAME).exists());
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn rolls_back_a_failed_patch_after_moving_files() {
+    fn install_and_patch(root: &Path) -> AppResult<()> {
+      let mut transaction = Transaction::begin(root)?;
+      let prepared = transaction.staging_dir().join("ccloader");
+      fs::create_dir(&prepared).unwrap();
+      fs::write(prepared.join("index.html"), "").unwrap();
+      transaction.install(&prepared, Path::new("ccloader"))?;
+      transaction.snapshot_file(Path::new("package.json"))?;
+      // the patch fails halfway through writing the file
+      fs::write(root.join("package.json"), "{\"main\":").unwrap();
+      bail!("couldn't write package.json");
+    }
+
+    let root = set_up("patch");
+    assert!(install_and_patch(&root).is_err());
+    assert!(!root.join("ccloader").exists());
+    assert_unchanged(&root);
+    assert!(!root.join(STAGING_DIR_NAME).exists());
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn recovers_an_interrupted_run_on_the_next_begin() {
+    let root = set_up("recover");
+    let mut transaction = Transaction::begin(&root).unwrap();
+    make_changes(&root, &mut transaction);
+    // neither committed nor rolled back, as if the process had been killed
+    std::mem::forget(transaction);
+    assert!(root.join(STAGING_DIR_NAME).join(JOURNAL_FILE_NAME).is_file());
+
+    let transaction = Transaction::begin(&root).unwrap();
+    assert_unchanged(&root);
+    transaction.commit();
+    assert!(!root.join(STAGING_DIR_NAME).exists());
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn keeps_the_changes_of_an_interrupted_commit() {
+    let root = set_up("committed");
+    let mut transaction = Transaction::begin(&root).unwrap();
+    make_changes(&root, &mut transaction);
+    // killed after the journal has been marked as committed
+    transaction.write_journal(true).unwrap();
+    std::mem::forget(transaction);
+
+    Transaction::begin(&root).unwrap().commit();
+    assert_eq!(read(&root.join("old.txt")), "new contents");
+    assert!(!root.join("gone.txt").exists());
+    assert!(!root.join(STAGING_DIR_NAME).exists());
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn keeps_backups_without_a_journal() {
+    let root = set_up("no-journal");
+    let mut transaction = Transaction::begin(&root).unwrap();
+    transaction.remove(Path::new("gone.txt")).unwrap();
+    std::mem::forget(transaction);
+    let staging_dir = root.join(STAGING_DIR_NAME);
+    fs::remove_file(staging_dir.join(JOURNAL_FILE_NAME)).unwrap();
+
+    assert!(Transaction::begin(&root).is_err());
+    assert!(!is_empty_dir(&staging_dir.join(BACKUPS_DIR_NAME)));
+    fs::remove_dir_all(&root).unwrap();
+  }
+}